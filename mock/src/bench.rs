@@ -0,0 +1,254 @@
+//! Counts the host calls of the objtar sample's workload, a gzipped tar of the parts of a content object
+//! written to the output stream, for each way of writing the stream.
+//!
+//! The workload runs natively against the mock fabric rather than through a wasm module so the writers
+//! may be swapped between runs.  Parts of a chosen size may be added to the context object first, the
+//! parts of the fixtures being too small to show a difference.
+
+use crate::fabric::{MockFabric, SharedFabric};
+
+use elvwasm::bccontext_fabric_io::FabricStreamWriter;
+use elvwasm::bccontext_testing::install_host;
+use elvwasm::{BitcodeContext, HashOrToken, QPartList};
+use flate2::write::GzEncoder;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufWriter, IoSlice, Read, Write};
+use std::rc::Rc;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Writer selects how the tar is written to the output stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Writer {
+    /// every write of the gzip encoder is a host call
+    Direct,
+    /// a BufWriter of the batch size, as the objtar sample does
+    Buffered,
+    /// a BatchedStreamWriter of the batch size
+    Batched,
+    /// an uncompressed tar written through a BatchedStreamWriter with one vectored write per part
+    Vectored,
+}
+
+pub const WRITERS: [Writer; 4] = [
+    Writer::Direct,
+    Writer::Buffered,
+    Writer::Batched,
+    Writer::Vectored,
+];
+
+#[derive(Debug)]
+pub struct BenchResult {
+    pub writer: Writer,
+    /// host calls writing the output stream
+    pub writes: usize,
+    /// every host call of the run, including the part reads
+    pub host_calls: usize,
+    /// bytes of the output stream
+    pub output: usize,
+}
+
+/// add_parts creates a new version of the context object with parts of pseudo random bytes, which the
+/// gzip encoder cannot shrink much
+pub fn add_parts(fab: &SharedFabric, parts: usize, part_size: usize) -> BoxResult<()> {
+    let _guard = fab.install();
+    let bcc = BitcodeContext::default();
+    let qwt = bcc.q_modify_content_typed()?.qwtoken;
+    let mut seed: u32 = 0x9e37_79b9;
+    for _ in 0..parts {
+        let data: Vec<u8> = (0..part_size)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        let mut pw = bcc.create_part_writer(&qwt)?;
+        pw.write_all(&data)?;
+        pw.finish()?;
+    }
+    bcc.q_finalize_content_typed(&qwt)?;
+    Ok(())
+}
+
+/// count_calls runs f with the fabric installed as the host, counting the host calls by operation
+fn count_calls<T>(
+    fab: &SharedFabric,
+    f: impl FnOnce() -> BoxResult<T>,
+) -> BoxResult<(T, BTreeMap<String, usize>)> {
+    let counts = Rc::new(RefCell::new(BTreeMap::new()));
+    let (host, c) = (fab.clone(), counts.clone());
+    let _guard = install_host(move |binding: &str, ns: &str, op: &str, msg: &[u8]| {
+        *c.borrow_mut().entry(op.to_string()).or_insert(0) += 1;
+        host.borrow_mut().host_call(binding, ns, op, msg)
+    });
+    let res = f()?;
+    let counts = counts.take();
+    Ok((res, counts))
+}
+
+/// tar_gz appends every part to a gzipped tar as the objtar sample does
+fn tar_gz<W: Write>(
+    bcc: &BitcodeContext,
+    qhot: &HashOrToken,
+    pl: &QPartList,
+    out: W,
+) -> BoxResult<()> {
+    let mut a = tar::Builder::new(GzEncoder::new(out, flate2::Compression::default()));
+    for part in &pl.part_list.parts {
+        let stream = bcc.new_stream()?;
+        bcc.write_part_to_stream(
+            stream.stream_id().to_string(),
+            &part.hash,
+            qhot,
+            0,
+            -1,
+            false,
+        )?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(part.size as u64);
+        header.set_cksum();
+        a.append_data(&mut header, part.hash.as_str(), stream.reader())?;
+    }
+    a.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+/// tar_vectored writes each part as its header, data and padding in a single vectored write
+fn tar_vectored<W: Write>(
+    bcc: &BitcodeContext,
+    qhot: &HashOrToken,
+    pl: &QPartList,
+    mut out: W,
+) -> BoxResult<()> {
+    for part in &pl.part_list.parts {
+        let stream = bcc.new_stream()?;
+        bcc.write_part_to_stream(
+            stream.stream_id().to_string(),
+            &part.hash,
+            qhot,
+            0,
+            -1,
+            false,
+        )?;
+        let mut data = Vec::new();
+        stream.reader().read_to_end(&mut data)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_path(part.hash.as_str())?;
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let padding = vec![0u8; (512 - data.len() % 512) % 512];
+        let slices = [
+            IoSlice::new(header.as_bytes()),
+            IoSlice::new(&data),
+            IoSlice::new(&padding),
+        ];
+        let expected = 512 + data.len() + padding.len();
+        let n = out.write_vectored(&slices)?;
+        if n != expected {
+            return Err(format!("short vectored write {n} of {expected}").into());
+        }
+    }
+    out.write_all(&[0u8; 1024])?;
+    out.flush()?;
+    Ok(())
+}
+
+/// objtar writes the tar of the parts of qhot to the output stream, returning its size
+fn objtar(
+    bcc: &BitcodeContext,
+    qhot: &HashOrToken,
+    writer: Writer,
+    batch: usize,
+) -> BoxResult<usize> {
+    let pl = bcc.q_part_list_typed(&qhot.clone().into())?;
+    let mut fw = FabricStreamWriter::new(bcc, "fos".to_string(), 0);
+    match writer {
+        Writer::Direct => tar_gz(bcc, qhot, &pl, &mut fw)?,
+        Writer::Buffered => tar_gz(bcc, qhot, &pl, BufWriter::with_capacity(batch, &mut fw))?,
+        Writer::Batched => {
+            let mut bw = fw.batched(batch);
+            tar_gz(bcc, qhot, &pl, &mut bw)?;
+            fw = bw.into_inner()?;
+        }
+        Writer::Vectored => {
+            let mut bw = fw.batched(batch);
+            tar_vectored(bcc, qhot, &pl, &mut bw)?;
+            fw = bw.into_inner()?;
+        }
+    }
+    Ok(fw.size)
+}
+
+/// bench runs the workload once per writer against the context object of the fabric
+pub fn bench(
+    fab: MockFabric,
+    parts: usize,
+    part_size: usize,
+    batch: usize,
+) -> BoxResult<Vec<BenchResult>> {
+    let fab = SharedFabric::new(fab);
+    if parts > 0 {
+        add_parts(&fab, parts, part_size)?;
+    }
+    let current = fab.borrow().current;
+    let qhot: HashOrToken = fab.borrow().fab.library.objects[current].hash.parse()?;
+    let bcc = BitcodeContext::default();
+    let mut results = Vec::new();
+    for writer in WRITERS {
+        fab.borrow_mut().reset_call();
+        let (output, calls) = count_calls(&fab, || objtar(&bcc, &qhot, writer, batch))?;
+        results.push(BenchResult {
+            writer,
+            writes: calls.get("Write").copied().unwrap_or_default(),
+            host_calls: calls.values().sum(),
+            output,
+        });
+    }
+    Ok(results)
+}
+
+pub fn print(results: &[BenchResult]) {
+    println!(
+        "{:<10} {:>8} {:>12} {:>14}",
+        "writer", "writes", "host calls", "output bytes"
+    );
+    for r in results {
+        println!(
+            "{:<10} {:>8} {:>12} {:>14}",
+            format!("{:?}", r.writer),
+            r.writes,
+            r.host_calls,
+            r.output
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_bench_objtar() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../samples/fixture");
+        let results = bench(MockFabric::load(&dir).unwrap(), 4, 100_000, 64 * 1024).unwrap();
+        let by_writer = |w: Writer| results.iter().find(|r| r.writer == w).unwrap();
+        let (direct, buffered, batched) = (
+            by_writer(Writer::Direct),
+            by_writer(Writer::Buffered),
+            by_writer(Writer::Batched),
+        );
+        // the same gzipped tar is written however the writes are grouped
+        assert_eq!(direct.output, batched.output);
+        assert_eq!(buffered.output, batched.output);
+        assert!(batched.writes < direct.writes);
+        // each added part flushes the buffered headers and padding then writes its data past the
+        // buffer, the fixture's small parts and the end of the archive are written with the last flush
+        let vectored = by_writer(Writer::Vectored);
+        assert_eq!(vectored.writes, 2 * 4 + 1);
+    }
+}
@@ -0,0 +1,222 @@
+//! A cassette is a JSONL recording of every host call made by a bitcode module, one [Entry] per line
+//! ```json
+//! {"binding":"id45678933","ns":"core","op":"SQMDGet","payload":"{\"jpc\":\"1.0\",...}","reply":"{\"result\":...}"}
+//! {"binding":"id45678933","ns":"fos","op":"Write","payload_base64":"iVBORw0K...","reply":"{\"written\":1024}"}
+//! ```
+//! Payloads and replies are stored as text when they are utf8 and as base64 otherwise.  A host call that
+//! failed records its error in place of the reply.
+//!
+//! [Recorder] writes a cassette while the mock fabric answers the calls.  [Replayer] answers the calls from a
+//! cassette instead, in the recorded order, and fails any call that does not match the next recorded one
+//! so that a captured session becomes a regression test of the module.
+
+use crate::fabric::HostResult;
+
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub binding: String,
+    pub ns: String,
+    pub op: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn encode(data: &[u8]) -> (Option<String>, Option<String>) {
+    match std::str::from_utf8(data) {
+        Ok(s) => (Some(s.to_string()), None),
+        Err(_) => (None, Some(base64::encode(data))),
+    }
+}
+
+fn decode(
+    text: &Option<String>,
+    b64: &Option<String>,
+) -> std::result::Result<Vec<u8>, base64::DecodeError> {
+    match (text, b64) {
+        (Some(s), _) => Ok(s.as_bytes().to_vec()),
+        (None, Some(b)) => base64::decode(b),
+        (None, None) => Ok(Vec::new()),
+    }
+}
+
+impl Entry {
+    pub fn new(binding: &str, ns: &str, op: &str, payload: &[u8], res: &HostResult) -> Entry {
+        let (payload, payload_base64) = encode(payload);
+        let mut e = Entry {
+            binding: binding.to_string(),
+            ns: ns.to_string(),
+            op: op.to_string(),
+            payload,
+            payload_base64,
+            ..Default::default()
+        };
+        match res {
+            Ok(r) => (e.reply, e.reply_base64) = encode(r),
+            Err(err) => e.error = Some(err.to_string()),
+        }
+        e
+    }
+
+    pub fn payload(&self) -> std::result::Result<Vec<u8>, base64::DecodeError> {
+        decode(&self.payload, &self.payload_base64)
+    }
+
+    /// result returns the recorded reply or error of the host call
+    pub fn result(&self) -> HostResult {
+        match &self.error {
+            Some(e) => Err(e.clone().into()),
+            None => Ok(decode(&self.reply, &self.reply_base64)?),
+        }
+    }
+}
+
+/// Recorder appends the host calls of a run to a cassette
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> std::io::Result<Recorder> {
+        Ok(Recorder {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, entry: &Entry) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.out, entry)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+}
+
+/// Replayer serves the replies of a cassette in order
+pub struct Replayer {
+    entries: VecDeque<Entry>,
+    /// descriptions of the calls that did not match the cassette
+    pub mismatches: Vec<String>,
+}
+
+impl Replayer {
+    pub fn load(
+        path: &Path,
+    ) -> std::result::Result<Replayer, Box<dyn std::error::Error + Send + Sync>> {
+        let mut entries = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push_back(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Replayer {
+            entries,
+            mismatches: Vec::new(),
+        })
+    }
+
+    /// host_call answers a host call with the next recorded reply.  The call must match the next recorded
+    /// namespace, op and payload, the binding is not compared.
+    pub fn host_call(&mut self, binding: &str, ns: &str, op: &str, payload: &[u8]) -> HostResult {
+        let mismatch = match self.entries.front() {
+            None => format!("unexpected call {ns} {op} after the end of the cassette"),
+            Some(e) if e.ns != ns || e.op != op => {
+                format!(
+                    "unexpected call {ns} {op}, the cassette expects {} {}",
+                    e.ns, e.op
+                )
+            }
+            Some(e) if e.payload()? != payload => {
+                let got = Entry::new(binding, ns, op, payload, &Ok(Vec::new()));
+                format!(
+                    "unexpected payload for {ns} {op}, got {:?} expected {:?}",
+                    got.payload.or(got.payload_base64),
+                    e.payload.as_ref().or(e.payload_base64.as_ref())
+                )
+            }
+            Some(_) => {
+                let e = self.entries.pop_front().unwrap_or_default();
+                return e.result();
+            }
+        };
+        self.mismatches.push(mismatch.clone());
+        Err(mismatch.into())
+    }
+
+    /// finish reports the mismatched calls and any recorded calls the run did not make
+    pub fn finish(&self) -> std::result::Result<(), String> {
+        let mut problems = self.mismatches.clone();
+        problems.extend(
+            self.entries
+                .iter()
+                .map(|e| format!("expected call {} {} was not made", e.ns, e.op)),
+        );
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_replay() {
+        let path = std::env::temp_dir().join(format!("cassette_{}.jsonl", std::process::id()));
+        let mut rec = Recorder::create(&path).unwrap();
+        let calls: Vec<(&str, &str, Vec<u8>, HostResult)> = vec![
+            (
+                "core",
+                "SQMDGet",
+                br#"{"path":"/a"}"#.to_vec(),
+                Ok(br#"{"result":1}"#.to_vec()),
+            ),
+            (
+                "fos",
+                "Write",
+                vec![0xff, 0x00, 0xfe],
+                Ok(br#"{"written":3}"#.to_vec()),
+            ),
+            ("core", "QPartList", b"{}".to_vec(), Err("no parts".into())),
+        ];
+        for (ns, op, payload, res) in &calls {
+            rec.record(&Entry::new("id1", ns, op, payload, res))
+                .unwrap();
+        }
+        drop(rec);
+
+        let mut rep = Replayer::load(&path).unwrap();
+        for (ns, op, payload, res) in &calls {
+            let got = rep.host_call("id2", ns, op, payload);
+            match res {
+                Ok(r) => assert_eq!(&got.unwrap(), r),
+                Err(e) => assert_eq!(got.unwrap_err().to_string(), e.to_string()),
+            }
+        }
+        assert!(rep.finish().is_ok());
+
+        let mut rep = Replayer::load(&path).unwrap();
+        assert!(rep.host_call("id1", "core", "SQMDSet", b"{}").is_err());
+        assert!(rep
+            .host_call("id1", "core", "SQMDGet", br#"{"path":"/b"}"#)
+            .is_err());
+        let problems = rep.finish().unwrap_err();
+        assert!(problems.contains("expected call fos Write was not made"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1497 @@
+//! MockFabric is an in-process stand in for the content fabric answering every host call made by elvwasm
+//! bitcode.  It is the host of the `mock` runner and may be installed as the
+//! [elvwasm::bccontext_testing::MockHost] of native tests.  A JSON fixture has the form
+//! ```json
+//! {
+//!   "library" : { "id" : "ilib...", "objects" : [ {
+//!       "id" : "iq__...", "hash" : "hq__...", "write_token" : "", "qlib_id" : "ilib...", "type" : "",
+//!       "meta" : {},
+//!       "parts" : [ { "data" : "<base64>" }, { "file" : "relative/path/to/bytes" } ],
+//!       "files" : { "assets/a.jpg" : { "mime" : "image/jpeg", "file" : "relative/path/a.jpg" } },
+//!       "versions" : [ "hq__older..." ]
+//!   } ] },
+//!   "responses" : { "ProxyHttp" : {}, "CallExternalBitcode" : {} },
+//!   "call" : { "jpc" : "1.0", "id" : "...", "method" : "...", "qinfo" : {}, "params" : {} }
+//! }
+//! ```
+//! `file` entries are relative to the directory containing the fixture.  `responses` supplies canned
+//! results overriding the default answers of the ext, search and external bitcode calls: ProxyHttp and
+//! RestCall answer an empty 200 response, CallExternalBitcode checks the object and code part and returns
+//! a null function_return, and the search calls act on an in-memory [MockIndex].
+//! State is loaded either from a JSON fixture as above or from a fixture directory laid out as
+//! ```text
+//! fabric.json                    library id, responses and call as above, objects are optional
+//! objects/<qid>/object.json      optional hash, write_token, type, versions and a "mime" map of file paths
+//! objects/<qid>/meta.json        optional metadata
+//! objects/<qid>/files/...        the file tree of the object
+//! objects/<qid>/parts/...        one part per file, in file name order
+//! ```
+//! An object without a hash is given one computed from its metadata and parts.
+//! The object addressed by the `qinfo` of the call is the context object of every host call.
+//! ```ignore
+//! let fab = SharedFabric::new(MockFabric::load(Path::new("samples/fixture"))?);
+//! let _guard = fab.install();
+//! // ... call handlers
+//! assert_eq!(fab.borrow().callback.as_ref().unwrap()["http"]["status"], 200);
+//! ```
+
+use elvwasm::bccontext_testing::{install_host, HostGuard, MockHost};
+use elvwasm::fabric_id::{encode_base58, ObjectHash, PartHash, WriteToken, ID_LEN};
+use elvwasm::{make_json_error, ErrorKinds};
+
+use crate::search::MockIndex;
+
+use md5::Md5;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use wapc_guest::CallResult;
+
+pub type HostResult = std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
+
+type OpResult = std::result::Result<Value, ErrorKinds>;
+
+/// The output stream of the bitcode call
+pub const FOS: &str = "fos";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Part {
+    #[serde(default)]
+    pub hash: String,
+    /// base64 encoded contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// path of the contents relative to the fixture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip)]
+    pub contents: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct File {
+    #[serde(default)]
+    pub mime: String,
+    /// base64 encoded contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// path of the contents relative to the fixture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip)]
+    pub contents: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Object {
+    #[serde(default)]
+    pub hash: String,
+    pub id: String,
+    #[serde(default)]
+    pub qlib_id: String,
+    #[serde(rename = "type", default)]
+    pub qtype: String,
+    #[serde(default)]
+    pub write_token: String,
+    #[serde(default)]
+    pub meta: Value,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+    #[serde(default)]
+    pub files: BTreeMap<String, File>,
+    /// previous version hashes, oldest first
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Library {
+    pub id: String,
+    #[serde(default)]
+    pub objects: Vec<Object>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RootMockFabric {
+    pub library: Library,
+    #[serde(default)]
+    pub responses: Map<String, Value>,
+    #[serde(default)]
+    pub call: Value,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub data: Vec<u8>,
+    pub pos: usize,
+    pub file_name: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MockFabric {
+    pub fab: RootMockFabric,
+    /// index of the context object in the library
+    pub current: usize,
+    pub streams: HashMap<String, Stream>,
+    pub qss: HashMap<String, HashMap<String, String>>,
+    /// the params of the last Callback
+    pub callback: Option<Value>,
+    /// the params of every StartBitcodeLRO
+    pub lros: Vec<Value>,
+    /// (level, message) of every Log
+    pub logs: Vec<(String, String)>,
+    /// (method, params) of every ProxyHttp, RestCall and CallExternalBitcode
+    pub ext_calls: Vec<(String, Value)>,
+    /// the index of the search calls
+    pub index: MockIndex,
+    counter: u64,
+}
+
+fn not_exist(what: &str) -> ErrorKinds {
+    ErrorKinds::NotExist(what.to_string())
+}
+
+fn param_str(p: &Value, key: &str) -> std::result::Result<String, ErrorKinds> {
+    match p.get(key) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(Value::Null) | None => Ok(String::new()),
+        Some(v) => Ok(v.to_string()),
+    }
+}
+
+fn param_u64(p: &Value, key: &str) -> u64 {
+    p.get(key).and_then(|v| v.as_u64()).unwrap_or_default()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn load_contents(
+    root: &Path,
+    data: &Option<String>,
+    file: &Option<String>,
+) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(d) = data {
+        return Ok(base64::decode(d)?);
+    }
+    if let Some(f) = file {
+        return Ok(std::fs::read(root.join(f))?);
+    }
+    Ok(Vec::new())
+}
+
+fn part_hash(contents: &[u8]) -> std::result::Result<String, ErrorKinds> {
+    let ph = PartHash {
+        encrypted: false,
+        digest: Sha256::digest(contents).to_vec(),
+        size: contents.len() as u64,
+    };
+    Ok(ph.to_qphash()?.to_string())
+}
+
+/// meta_pointer converts a fabric meta path `/a/b` to a json pointer
+fn meta_pointer(path: &str) -> String {
+    let p = path.trim_end_matches('/');
+    if p.is_empty() || p.starts_with('/') {
+        p.to_string()
+    } else {
+        format!("/{p}")
+    }
+}
+
+fn set_at(root: &mut Value, path: &str, val: Value) {
+    let mut cur = root;
+    for key in meta_pointer(path).split('/').skip(1) {
+        if !cur.is_object() {
+            *cur = json!({});
+        }
+        cur = cur
+            .as_object_mut()
+            .unwrap()
+            .entry(key.to_string())
+            .or_insert(Value::Null);
+    }
+    *cur = val;
+}
+
+fn merge(dst: &mut Value, src: Value) {
+    match (dst, src) {
+        (Value::Object(d), Value::Object(s)) => {
+            for (k, v) in s {
+                merge(d.entry(k).or_insert(Value::Null), v);
+            }
+        }
+        (d, s) => *d = s,
+    }
+}
+
+/// content_hash computes the version hash of an object from its metadata and parts
+fn content_hash(o: &Object) -> std::result::Result<String, ErrorKinds> {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&o.meta).unwrap_or_default());
+    let mut size = 0;
+    for p in &o.parts {
+        hasher.update(p.hash.as_bytes());
+        size += p.contents.len() as u64;
+    }
+    let oh = ObjectHash {
+        digest: hasher.finalize().to_vec(),
+        size,
+        qid: o.id.parse()?,
+    };
+    Ok(oh.to_qhash()?.to_string())
+}
+
+fn guess_mime(path: &str) -> &'static str {
+    let ext = path.rsplit_once('.').map(|(_, e)| e.to_lowercase());
+    match ext.as_deref() {
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("html") => "text/html",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("mp4") => "video/mp4",
+        Some("tar") => "application/x-tar",
+        _ => "application/octet-stream",
+    }
+}
+
+/// walk_files returns the paths of the files below dir relative to it, sorted
+fn walk_files(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut out = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(d) = pending.pop() {
+        for entry in std::fs::read_dir(&d)? {
+            let p = entry?.path();
+            if p.is_dir() {
+                pending.push(p);
+            } else if let Ok(rel) = p.strip_prefix(dir) {
+                let parts: Vec<String> = rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect();
+                out.push(parts.join("/"));
+            }
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+#[derive(Deserialize, Default)]
+struct ObjectFile {
+    #[serde(default)]
+    hash: String,
+    #[serde(default)]
+    qlib_id: String,
+    #[serde(rename = "type", default)]
+    qtype: String,
+    #[serde(default)]
+    write_token: String,
+    #[serde(default)]
+    versions: Vec<String>,
+    /// mime types of files by path, guessed from the extension otherwise
+    #[serde(default)]
+    mime: HashMap<String, String>,
+}
+
+/// load_object_dir reads an `objects/<qid>` directory of a fixture directory
+fn load_object_dir(
+    dir: &Path,
+) -> std::result::Result<Object, Box<dyn std::error::Error + Send + Sync>> {
+    let of: ObjectFile = match std::fs::read(dir.join("object.json")) {
+        Ok(b) => serde_json::from_slice(&b)?,
+        Err(_) => ObjectFile::default(),
+    };
+    let meta = match std::fs::read(dir.join("meta.json")) {
+        Ok(b) => serde_json::from_slice(&b)?,
+        Err(_) => json!({}),
+    };
+    let mut o = Object {
+        hash: of.hash,
+        id: dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        qlib_id: of.qlib_id,
+        qtype: of.qtype,
+        write_token: of.write_token,
+        meta,
+        versions: of.versions,
+        ..Default::default()
+    };
+    let files = dir.join("files");
+    if files.is_dir() {
+        for path in walk_files(&files)? {
+            let mime = match of.mime.get(&path) {
+                Some(m) => m.clone(),
+                None => guess_mime(&path).to_string(),
+            };
+            let file = File {
+                mime,
+                contents: std::fs::read(files.join(&path))?,
+                ..Default::default()
+            };
+            o.files.insert(path, file);
+        }
+    }
+    let parts = dir.join("parts");
+    if parts.is_dir() {
+        for path in walk_files(&parts)? {
+            o.parts.push(Part {
+                contents: std::fs::read(parts.join(&path))?,
+                ..Default::default()
+            });
+        }
+    }
+    Ok(o)
+}
+
+/// json_path evaluates the subset of JSONPath made of `$`, `.key`, `['key']` and `[index]`
+pub fn json_path(v: &Value, query: &str) -> OpResult {
+    let q = query
+        .strip_prefix('$')
+        .ok_or_else(|| ErrorKinds::Invalid(format!("query {query} must start with $")))?;
+    let mut cur = v;
+    let mut rest = q;
+    while !rest.is_empty() {
+        let (next, remaining) = if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            (cur.get(&r[..end]), &r[end..])
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r
+                .find(']')
+                .ok_or_else(|| ErrorKinds::Invalid(format!("unterminated [ in {query}")))?;
+            let sel = &r[..end];
+            let key = sel.trim_matches(|c: char| c == '\'' || c == '"');
+            let next = if key.len() != sel.len() {
+                cur.get(key)
+            } else {
+                let idx: usize = sel
+                    .parse()
+                    .map_err(|_| ErrorKinds::Invalid(format!("bad index {sel} in {query}")))?;
+                cur.get(idx)
+            };
+            (next, &r[end + 1..])
+        } else {
+            return Err(ErrorKinds::Invalid(format!("unsupported query {query}")));
+        };
+        cur = match next {
+            Some(n) => n,
+            None => return Ok(Value::Null),
+        };
+        rest = remaining;
+    }
+    Ok(cur.clone())
+}
+
+impl MockFabric {
+    /// load reads a fixture file, resolving part and file contents relative to its directory, or a fixture
+    /// directory
+    pub fn load(
+        path: &Path,
+    ) -> std::result::Result<MockFabric, Box<dyn std::error::Error + Send + Sync>> {
+        if path.is_dir() {
+            return MockFabric::load_dir(path);
+        }
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let fab: RootMockFabric = serde_json::from_reader(reader)?;
+        let root = path.parent().map(PathBuf::from).unwrap_or_default();
+        MockFabric::from_fixture(fab, &root)
+    }
+
+    fn load_dir(
+        dir: &Path,
+    ) -> std::result::Result<MockFabric, Box<dyn std::error::Error + Send + Sync>> {
+        let mut fab: RootMockFabric = match std::fs::read(dir.join("fabric.json")) {
+            Ok(b) => serde_json::from_slice(&b)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RootMockFabric::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let objects = dir.join("objects");
+        if objects.is_dir() {
+            let mut dirs: Vec<PathBuf> = std::fs::read_dir(&objects)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<std::io::Result<_>>()?;
+            dirs.sort();
+            for d in dirs.into_iter().filter(|d| d.is_dir()) {
+                fab.library.objects.push(load_object_dir(&d)?);
+            }
+        }
+        MockFabric::from_fixture(fab, dir)
+    }
+
+    pub fn from_fixture(
+        mut fab: RootMockFabric,
+        root: &Path,
+    ) -> std::result::Result<MockFabric, Box<dyn std::error::Error + Send + Sync>> {
+        // host calls act on a context object so an empty library is given an empty one
+        if fab.library.objects.is_empty() {
+            fab.library.objects.push(Object::default());
+        }
+        for o in fab.library.objects.iter_mut() {
+            if o.qlib_id.is_empty() {
+                o.qlib_id = fab.library.id.clone();
+            }
+            if o.meta.is_null() {
+                o.meta = json!({});
+            }
+            // contents read from a fixture directory have neither data nor file
+            for p in o.parts.iter_mut() {
+                if p.data.is_some() || p.file.is_some() {
+                    p.contents = load_contents(root, &p.data, &p.file)?;
+                }
+                if p.hash.is_empty() {
+                    p.hash = part_hash(&p.contents)?;
+                }
+            }
+            for f in o.files.values_mut() {
+                if f.data.is_some() || f.file.is_some() {
+                    f.contents = load_contents(root, &f.data, &f.file)?;
+                }
+            }
+            if o.hash.is_empty() && o.write_token.is_empty() && !o.id.is_empty() {
+                o.hash = content_hash(o)?;
+            }
+        }
+        let qinfo = fab.call.get("qinfo").cloned().unwrap_or_default();
+        let current = fab
+            .library
+            .objects
+            .iter()
+            .position(|o| {
+                ["write_token", "hash", "id"].iter().any(|k| {
+                    let v = param_str(&qinfo, k).unwrap_or_default();
+                    !v.is_empty() && [&o.write_token, &o.hash, &o.id].contains(&&v)
+                })
+            })
+            .unwrap_or_default();
+        let mut mf = MockFabric {
+            fab,
+            current,
+            ..Default::default()
+        };
+        mf.streams.insert(FOS.to_string(), Stream::default());
+        Ok(mf)
+    }
+
+    /// output returns the bytes written to the output stream
+    pub fn output(&self) -> &[u8] {
+        self.streams
+            .get(FOS)
+            .map(|s| s.data.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// reset_call clears the output stream and callback of a previous call, keeping the content
+    pub fn reset_call(&mut self) {
+        self.streams.insert(FOS.to_string(), Stream::default());
+        self.callback = None;
+    }
+
+    /// request returns the fixture's call as the jpc request to send to the bitcode
+    pub fn request(&self) -> &Value {
+        &self.fab.call
+    }
+
+    /// host_call answers a single waPC host call
+    /// # Arguments
+    /// * `binding` - the request id of the bitcode call
+    /// * `ns` - the fabric module, or the stream id for stream operations
+    /// * `op` - the fabric method
+    /// * `payload` - the jpc request, or the raw bytes for stream writes
+    pub fn host_call(&mut self, binding: &str, ns: &str, op: &str, payload: &[u8]) -> HostResult {
+        let res = match op {
+            "Write" => return Ok(self.stream_write(ns, payload)?.to_string().into_bytes()),
+            "Reader" => return Ok(self.stream_read(ns, payload)?),
+            "Seek" => return Ok(self.stream_seek(ns, payload)?.to_string().into_bytes()),
+            "Read" => {
+                return Ok(self
+                    .stream_read_inline(ns, payload)?
+                    .to_string()
+                    .into_bytes())
+            }
+            _ => {
+                let req: Value = serde_json::from_slice(payload)?;
+                // CallExternalBitcode sends its parameters unwrapped
+                let params = match req.get("params") {
+                    Some(p) if op != "CallExternalBitcode" => p.clone(),
+                    _ => req,
+                };
+                self.dispatch(op, &params)
+            }
+        };
+        match res {
+            Ok(v) => Ok(serde_json::to_vec(
+                &json!({ "result": v, "jpc": "1.0", "id": binding }),
+            )?),
+            Err(e) => make_json_error(e, binding),
+        }
+    }
+
+    fn dispatch(&mut self, op: &str, p: &Value) -> OpResult {
+        match op {
+            // ctx
+            "Log" => {
+                let (level, msg) = (param_str(p, "level")?, param_str(p, "msg")?);
+                self.logs.push((level, msg));
+                Ok(json!({}))
+            }
+            "NewStream" => Ok(json!({ "stream_id": self.new_stream(None) })),
+            "NewFileStream" => {
+                let name = format!("file_{}", self.next());
+                let sid = self.new_stream(Some(name.clone()));
+                Ok(json!({ "stream_id": sid, "file_name": name }))
+            }
+            "CloseStream" => {
+                let sid = param_str(p, "stream_id")?;
+                let s = self.stream(&sid)?;
+                let sum = to_hex(&Sha256::digest(&s.data));
+                if sid != FOS && s.file_name.is_none() {
+                    self.streams.remove(&sid);
+                }
+                Ok(json!(sum))
+            }
+            "FileStreamSize" => {
+                let len = self.file_stream(&param_str(p, "file_name")?)?.data.len();
+                Ok(json!({ "file_size": len }))
+            }
+            "Callback" => {
+                self.callback = Some(p.clone());
+                Ok(json!({}))
+            }
+            // core content lifecycle
+            "QCreateContent" => self.create_content(p),
+            "QModifyContent" => self.modify_content(p),
+            "QFinalizeContent" => match p.get("qwtoken") {
+                Some(_) => self.finalize(&param_str(p, "qwtoken")?),
+                // q_commit_content sends the hash of finalized content
+                None => self.commit(&param_str(p, "qhash")?),
+            },
+            "QListContent" => Ok(self.list()),
+            "QListContentFor" => {
+                if param_str(p, "external_lib")? == self.fab.library.id {
+                    Ok(self.list())
+                } else {
+                    Ok(json!({ "filter": "", "contents": [], "errors": [] }))
+                }
+            }
+            "QGetVersions" => {
+                let o = &self.fab.library.objects[self.find(&param_str(p, "qid")?)?];
+                let versions: Vec<Value> = o
+                    .versions
+                    .iter()
+                    .chain(std::iter::once(&o.hash))
+                    .filter(|h| !h.is_empty())
+                    .map(|h| {
+                        let mut q = Self::q_json(o);
+                        q["hash"] = json!(h);
+                        q
+                    })
+                    .collect();
+                Ok(json!({ "id": o.id, "versions": versions }))
+            }
+            // core parts and files
+            "QPartList" => {
+                let o =
+                    &self.fab.library.objects[self.find(&param_str(p, "object_id_or_hash")?)?];
+                let parts: Vec<Value> = o
+                    .parts
+                    .iter()
+                    .map(|pt| json!({ "hash": pt.hash, "size": pt.contents.len() }))
+                    .collect();
+                Ok(json!({ "part_list": { "content": Self::q_json(o), "parts": parts } }))
+            }
+            "QWritePartToStream" => {
+                let qphash = param_str(p, "qphash")?;
+                let data = self
+                    .part(&param_str(p, "qihot")?, &qphash)?
+                    .contents
+                    .clone();
+                let off = (param_u64(p, "off") as usize).min(data.len());
+                let end = match p.get("len").and_then(|v| v.as_i64()) {
+                    Some(l) if l >= 0 => (off + l as usize).min(data.len()),
+                    _ => data.len(),
+                };
+                let written = self.append(&param_str(p, "stream_id")?, &data[off..end])?;
+                Ok(json!({ "written": written }))
+            }
+            "QFileToStream" => {
+                // write_qfile_to_stream names the version qihot
+                let hot = match p.get("hash_or_token") {
+                    Some(_) => param_str(p, "hash_or_token")?,
+                    None => param_str(p, "qihot")?,
+                };
+                let idx = self.find(&hot)?;
+                let path = param_str(p, "path")?;
+                let f = self.fab.library.objects[idx]
+                    .files
+                    .get(path.trim_start_matches('/'))
+                    .ok_or_else(|| not_exist(&format!("file {path}")))?
+                    .clone();
+                let written = self.append(&param_str(p, "stream_id")?, &f.contents)?;
+                Ok(json!({ "written": written, "mime_type": f.mime }))
+            }
+            "FileToStream" => {
+                let data = self.file_stream(&param_str(p, "path")?)?.data.clone();
+                let written = self.append(&param_str(p, "stream_id")?, &data)?;
+                Ok(json!({ "written": written }))
+            }
+            "QCreatePartFromStream" => {
+                let idx = self.find_writable(&param_str(p, "qwtoken")?)?;
+                let data = self.stream(&param_str(p, "stream_id")?)?.data.clone();
+                let part = self.add_part(idx, data)?;
+                Ok(json!({ "qphash": part.hash, "size": part.contents.len() }))
+            }
+            "QCreateFileFromStream" => {
+                let idx = self.find_writable(&param_str(p, "qwtoken")?)?;
+                let data = self.stream(&param_str(p, "stream_id")?)?.data.clone();
+                let path = param_str(p, "path")?.trim_start_matches('/').to_string();
+                let part = self.add_part(idx, data.clone())?;
+                self.fab.library.objects[idx].files.insert(
+                    path,
+                    File {
+                        mime: param_str(p, "mime")?,
+                        contents: data,
+                        ..Default::default()
+                    },
+                );
+                let o = &self.fab.library.objects[idx];
+                Ok(json!({
+                    "content": Self::q_json(o),
+                    "part": { "hash": part.hash, "size": part.contents.len(), "write_token": o.write_token },
+                }))
+            }
+            "QCheckSumPart" => {
+                let data = &self.part("", &param_str(p, "qphash")?)?.contents;
+                Self::checksum(&param_str(p, "method")?, data)
+            }
+            "QCheckSumFile" => {
+                let path = param_str(p, "file_path")?;
+                let f = self.fab.library.objects[self.current]
+                    .files
+                    .get(path.trim_start_matches('/'))
+                    .ok_or_else(|| not_exist(&format!("file {path}")))?;
+                Self::checksum(&param_str(p, "method")?, &f.contents)
+            }
+            // core meta
+            "SQMDGet" | "SQMDGetJSONResolve" => {
+                let meta = &self.fab.library.objects[self.current].meta;
+                Self::meta_get(meta, &param_str(p, "path")?)
+            }
+            "SQMDGetExternal" => {
+                let idx = self.find(&param_str(p, "qhash")?)?;
+                Self::meta_get(&self.fab.library.objects[idx].meta, &param_str(p, "path")?)
+            }
+            "SQMDQuery" => json_path(
+                &self.fab.library.objects[self.current].meta,
+                &param_str(p, "query")?,
+            ),
+            "SQMDSet" => {
+                let meta = &mut self.fab.library.objects[self.current].meta;
+                set_at(
+                    meta,
+                    &param_str(p, "path")?,
+                    p.get("meta").cloned().unwrap_or_default(),
+                );
+                Ok(json!({}))
+            }
+            "SQMDMerge" => {
+                let val = match p.get("meta") {
+                    Some(Value::String(s)) => serde_json::from_str(s)
+                        .map_err(|e| ErrorKinds::Invalid(format!("merge meta is not json: {e}")))?,
+                    Some(v) => v.clone(),
+                    None => json!({}),
+                };
+                let path = meta_pointer(&param_str(p, "path")?);
+                let meta = &mut self.fab.library.objects[self.current].meta;
+                match meta.pointer_mut(&path) {
+                    Some(dst) => merge(dst, val),
+                    None => set_at(meta, &path, val),
+                }
+                Ok(json!({}))
+            }
+            "SQMDDelete" | "SQMDClear" => {
+                let path = meta_pointer(&param_str(p, "path")?);
+                let meta = &mut self.fab.library.objects[self.current].meta;
+                if op == "SQMDClear" {
+                    set_at(meta, &path, json!({}));
+                } else {
+                    let (parent, key) = path.rsplit_once('/').unwrap_or_default();
+                    meta.pointer_mut(parent)
+                        .and_then(|v| v.as_object_mut())
+                        .and_then(|m| m.remove(key))
+                        .ok_or_else(|| not_exist(&format!("meta {path}")))?;
+                }
+                Ok(json!({}))
+            }
+            "FetchLink" => self.fetch_link(p),
+            "SystemTime" => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                Ok(json!({ "time": now.as_millis() as u64 }))
+            }
+            // core state store
+            "QCreateQStateStore" => {
+                let id = encode_base58(&self.next_bytes(3));
+                self.qss.insert(id.clone(), HashMap::new());
+                Ok(json!(id))
+            }
+            "QSSSet" | "QSSGet" | "QSSDelete" => {
+                let (qssid, key) = (param_str(p, "qssid")?, param_str(p, "key")?);
+                let store = self
+                    .qss
+                    .get_mut(&qssid)
+                    .ok_or_else(|| not_exist(&format!("state store {qssid}")))?;
+                match op {
+                    "QSSSet" => {
+                        store.insert(key, param_str(p, "val")?);
+                        Ok(json!({}))
+                    }
+                    "QSSGet" => store
+                        .get(&key)
+                        .map(|v| json!(v))
+                        .ok_or_else(|| not_exist(&format!("key {key}"))),
+                    _ => {
+                        store.remove(&key);
+                        Ok(json!({}))
+                    }
+                }
+            }
+            // lro
+            "StartBitcodeLRO" => {
+                self.lros.push(p.clone());
+                Ok(json!({ "lro_handle": format!("tlro{}", encode_base58(&self.next_bytes(4))) }))
+            }
+            // ext, search and external bitcode are answered from the fixture when it has a response
+            _ => match self.fab.responses.get(op) {
+                Some(v) => Ok(v.clone()),
+                None => self.ext(op, p),
+            },
+        }
+    }
+
+    /// ext gives the default answers of the ext, search and external bitcode calls
+    fn ext(&mut self, op: &str, p: &Value) -> OpResult {
+        match op {
+            "ProxyHttp" | "RestCall" => {
+                self.ext_calls.push((op.to_string(), p.clone()));
+                Ok(json!({ "status": 200, "headers": {}, "body": "" }))
+            }
+            "CallExternalBitcode" => {
+                let hash = param_str(p, "object_hash")?;
+                self.find(&hash)?;
+                self.part(&hash, &param_str(p, "code_part_hash")?)?;
+                self.ext_calls.push((op.to_string(), p.clone()));
+                Ok(json!({ "function_return": null, "fout": "", "format": [] }))
+            }
+            _ => {
+                let body = self.search(op, p)?;
+                Ok(json!({ "http": { "status": 200, "body": body } }))
+            }
+        }
+    }
+
+    /// search answers the search calls, returning the body of the http reply
+    fn search(&mut self, op: &str, p: &Value) -> OpResult {
+        match op {
+            "NewIndexBuilder" => {
+                self.index = MockIndex::new(format!("/tmp/mock_index_{}", self.next()));
+                Ok(json!({ "dir": self.index.dir }))
+            }
+            "ArchiveIndexToPart" => {
+                let dir = param_str(p, "directory")?;
+                if dir != self.index.dir {
+                    return Err(not_exist(&format!("index directory {dir}")));
+                }
+                let part = self.add_part(self.current, self.index.archive())?;
+                Ok(json!({ "qphash": part.hash, "size": part.contents.len() }))
+            }
+            "RestoreIndexFromPart" => {
+                let part =
+                    self.part(&param_str(p, "content-hash")?, &param_str(p, "part-hash")?)?;
+                self.index = MockIndex::restore(&part.contents)?;
+                Ok(json!({ "dir": self.index.dir }))
+            }
+            _ => self.index.call(op, p),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+
+    fn next_bytes(&mut self, tag: u8) -> [u8; ID_LEN] {
+        let mut b = [0u8; ID_LEN];
+        b[0] = tag;
+        b[ID_LEN - 8..].copy_from_slice(&self.next().to_be_bytes());
+        b
+    }
+
+    fn new_qid(&mut self) -> String {
+        format!("iq__{}", encode_base58(&self.next_bytes(1)))
+    }
+
+    fn new_token(&mut self, qid: &str) -> std::result::Result<String, ErrorKinds> {
+        let wt = WriteToken {
+            qid: qid.parse()?,
+            nid: format!("inod{}", encode_base58(&[2; ID_LEN])),
+            random: self.next().to_be_bytes().to_vec(),
+        };
+        Ok(wt.to_qwtoken()?.to_string())
+    }
+
+    fn new_stream(&mut self, file_name: Option<String>) -> String {
+        let sid = format!("sid_{}", self.next());
+        self.streams.insert(
+            sid.clone(),
+            Stream {
+                file_name,
+                ..Default::default()
+            },
+        );
+        sid
+    }
+
+    fn stream(&mut self, sid: &str) -> std::result::Result<&mut Stream, ErrorKinds> {
+        self.streams
+            .get_mut(sid)
+            .ok_or_else(|| not_exist(&format!("stream {sid}")))
+    }
+
+    fn file_stream(&mut self, name: &str) -> std::result::Result<&mut Stream, ErrorKinds> {
+        self.streams
+            .values_mut()
+            .find(|s| s.file_name.as_deref() == Some(name))
+            .ok_or_else(|| not_exist(&format!("file stream {name}")))
+    }
+
+    fn append(&mut self, sid: &str, data: &[u8]) -> std::result::Result<usize, ErrorKinds> {
+        let s = self.stream(sid)?;
+        s.data.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn stream_write(&mut self, sid: &str, data: &[u8]) -> OpResult {
+        let s = self.stream(sid)?;
+        let end = s.pos + data.len();
+        if s.data.len() < end {
+            s.data.resize(end, 0);
+        }
+        s.data[s.pos..end].copy_from_slice(data);
+        s.pos = end;
+        Ok(json!({ "written": data.len() }))
+    }
+
+    fn read_len(payload: &[u8]) -> usize {
+        serde_json::from_slice::<Value>(payload)
+            .map(|v| param_u64(&v, "len") as usize)
+            .unwrap_or_default()
+    }
+
+    fn stream_read(
+        &mut self,
+        sid: &str,
+        payload: &[u8],
+    ) -> std::result::Result<Vec<u8>, ErrorKinds> {
+        let len = Self::read_len(payload);
+        let s = self.stream(sid)?;
+        let start = s.pos.min(s.data.len());
+        let end = if len == 0 {
+            s.data.len()
+        } else {
+            (start + len).min(s.data.len())
+        };
+        s.pos = end;
+        Ok(s.data[start..end].to_vec())
+    }
+
+    fn stream_read_inline(&mut self, sid: &str, payload: &[u8]) -> OpResult {
+        let data = self.stream_read(sid, payload)?;
+        Ok(json!({ "return": { "read": data.len() }, "result": base64::encode(&data) }))
+    }
+
+    fn stream_seek(&mut self, sid: &str, payload: &[u8]) -> OpResult {
+        let v: Value = serde_json::from_slice(payload)
+            .map_err(|e| ErrorKinds::Invalid(format!("bad seek request: {e}")))?;
+        let offset = v.get("offset").and_then(|o| o.as_i64()).unwrap_or_default();
+        let s = self.stream(sid)?;
+        let base = match v.get("whence").and_then(|w| w.as_i64()).unwrap_or_default() {
+            0 => 0,
+            1 => s.pos as i64,
+            _ => s.data.len() as i64,
+        };
+        let pos = base + offset;
+        if pos < 0 {
+            return Err(ErrorKinds::Invalid(format!(
+                "seek to negative offset {pos}"
+            )));
+        }
+        s.pos = pos as usize;
+        Ok(json!({ "offset": pos }))
+    }
+
+    /// find locates an object by id, hash, previous version hash or write token, "" is the context object
+    fn find(&self, r: &str) -> std::result::Result<usize, ErrorKinds> {
+        if r.is_empty() {
+            return Ok(self.current);
+        }
+        self.fab
+            .library
+            .objects
+            .iter()
+            .position(|o| {
+                o.id == r || o.hash == r || o.write_token == r || o.versions.iter().any(|v| v == r)
+            })
+            .ok_or_else(|| not_exist(&format!("content {r}")))
+    }
+
+    fn find_writable(&self, qwtoken: &str) -> std::result::Result<usize, ErrorKinds> {
+        let idx = self.find(qwtoken)?;
+        if self.fab.library.objects[idx].write_token.is_empty() {
+            return Err(ErrorKinds::Finalized(format!(
+                "content {} is not open for writing",
+                self.fab.library.objects[idx].id
+            )));
+        }
+        Ok(idx)
+    }
+
+    fn part(&self, hot: &str, qphash: &str) -> std::result::Result<&Part, ErrorKinds> {
+        let objects = &self.fab.library.objects;
+        let candidates: Vec<&Object> = match self.find(hot) {
+            Ok(idx) if !hot.is_empty() => vec![&objects[idx]],
+            _ => objects.iter().collect(),
+        };
+        candidates
+            .into_iter()
+            .flat_map(|o| o.parts.iter())
+            .find(|p| p.hash == qphash)
+            .ok_or_else(|| not_exist(&format!("part {qphash}")))
+    }
+
+    fn add_part(&mut self, idx: usize, contents: Vec<u8>) -> std::result::Result<Part, ErrorKinds> {
+        let part = Part {
+            hash: part_hash(&contents)?,
+            contents,
+            ..Default::default()
+        };
+        self.fab.library.objects[idx].parts.push(part.clone());
+        Ok(part)
+    }
+
+    fn create_content(&mut self, p: &Value) -> OpResult {
+        let id = self.new_qid();
+        let token = self.new_token(&id)?;
+        let meta = match p.get("meta") {
+            Some(m) if m.is_object() => m.clone(),
+            _ => json!({}),
+        };
+        self.fab.library.objects.push(Object {
+            id: id.clone(),
+            qlib_id: self.fab.library.id.clone(),
+            qtype: param_str(p, "qtype")?,
+            write_token: token.clone(),
+            meta,
+            ..Default::default()
+        });
+        Ok(json!({ "qid": id, "qwtoken": token }))
+    }
+
+    /// modify_content opens the context object for writing, applying the qtype and metadata given
+    fn modify_content(&mut self, p: &Value) -> OpResult {
+        let idx = self.current;
+        if self.fab.library.objects[idx].write_token.is_empty() {
+            let qid = self.fab.library.objects[idx].id.clone();
+            let token = self.new_token(&qid)?;
+            self.fab.library.objects[idx].write_token = token;
+        }
+        let qtype = param_str(p, "qtype")?;
+        let o = &mut self.fab.library.objects[idx];
+        if !qtype.is_empty() {
+            o.qtype = qtype;
+        }
+        if let Some(meta) = p.get("meta").filter(|m| m.is_object()) {
+            merge(&mut o.meta, meta.clone());
+        }
+        Ok(json!({ "qwtoken": o.write_token }))
+    }
+
+    fn finalize(&mut self, qwtoken: &str) -> OpResult {
+        let idx = self.find_writable(qwtoken)?;
+        let o = &mut self.fab.library.objects[idx];
+        let hash = content_hash(o)?;
+        if !o.hash.is_empty() {
+            let prev = std::mem::take(&mut o.hash);
+            o.versions.push(prev);
+        }
+        o.hash = hash.clone();
+        o.write_token.clear();
+        Ok(json!({ "qid": o.id, "qhash": hash }))
+    }
+
+    /// commit answers for a finalized version, which the mock holds as committed once finalized
+    fn commit(&self, qhash: &str) -> OpResult {
+        let o = self
+            .fab
+            .library
+            .objects
+            .iter()
+            .find(|o| {
+                !qhash.is_empty() && (o.hash == qhash || o.versions.iter().any(|v| v == qhash))
+            })
+            .ok_or_else(|| not_exist(&format!("content version {qhash}")))?;
+        Ok(json!({ "qid": o.id, "qhash": qhash }))
+    }
+
+    fn q_json(o: &Object) -> Value {
+        let size: usize = o.parts.iter().map(|p| p.contents.len()).sum();
+        json!({
+            "id": o.id,
+            "hash": o.hash,
+            "write_token": o.write_token,
+            "type": o.qtype,
+            "qlib_id": o.qlib_id,
+            "meta": o.meta,
+            "size_stats": { "parts": o.parts.len(), "size": format!("{size} B"), "size_bytes": size },
+        })
+    }
+
+    fn list(&self) -> Value {
+        let contents: Vec<Value> = self
+            .fab
+            .library
+            .objects
+            .iter()
+            .map(|o| json!({ "id": o.id, "versions": [Self::q_json(o)] }))
+            .collect();
+        json!({ "filter": "", "contents": contents, "errors": [] })
+    }
+
+    fn meta_get(meta: &Value, path: &str) -> OpResult {
+        meta.pointer(&meta_pointer(path))
+            .cloned()
+            .ok_or_else(|| not_exist(&format!("meta {path}")))
+    }
+
+    fn checksum(method: &str, data: &[u8]) -> OpResult {
+        match method.to_uppercase().as_str() {
+            "MD5" => Ok(json!(to_hex(&Md5::digest(data)))),
+            "SHA256" | "" => Ok(json!(to_hex(&Sha256::digest(data)))),
+            m => Err(ErrorKinds::Invalid(format!(
+                "unsupported checksum method {m}"
+            ))),
+        }
+    }
+
+    /// fetch_link resolves `./meta/...` and `./files/...` links against the context object or
+    /// `/qfab/<hash>/...` links against the addressed object.  File bodies are base64 encoded.
+    fn fetch_link(&self, p: &Value) -> OpResult {
+        let link = match p.get("link") {
+            Some(Value::Object(m)) => m
+                .get("/")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            Some(Value::String(s)) => s.clone(),
+            _ => return Err(ErrorKinds::Invalid("link missing".to_string())),
+        };
+        let (idx, rest) = if let Some(r) = link.strip_prefix("./") {
+            (self.current, r.to_string())
+        } else if let Some(r) = link.strip_prefix("/qfab/") {
+            let (hash, rest) = r.split_once('/').unwrap_or((r, ""));
+            (self.find(hash)?, rest.to_string())
+        } else {
+            return Err(ErrorKinds::Invalid(format!("unsupported link {link}")));
+        };
+        let o = &self.fab.library.objects[idx];
+        if let Some(path) = rest.strip_prefix("meta") {
+            let v = Self::meta_get(&o.meta, path)?;
+            return Ok(json!({
+                "status": 200,
+                "headers": { "Content-Type": ["application/json"] },
+                "body": v.to_string(),
+            }));
+        }
+        let path = rest
+            .strip_prefix("files/")
+            .ok_or_else(|| ErrorKinds::Invalid(format!("unsupported link {link}")))?;
+        let f = o
+            .files
+            .get(path)
+            .ok_or_else(|| not_exist(&format!("file {path}")))?;
+        Ok(json!({
+            "status": 200,
+            "headers": { "Content-Type": [f.mime], "Content-Length": [f.contents.len().to_string()] },
+            "body": base64::encode(&f.contents),
+        }))
+    }
+}
+
+impl MockHost for MockFabric {
+    fn host_call(&mut self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult {
+        MockFabric::host_call(self, binding, ns, op, msg)
+    }
+}
+
+/// SharedFabric is a handle to a [MockFabric] that may be installed as the host while the test keeps a
+/// clone to inspect the fabric
+#[derive(Clone, Default)]
+pub struct SharedFabric(pub Rc<RefCell<MockFabric>>);
+
+impl SharedFabric {
+    pub fn new(fab: MockFabric) -> SharedFabric {
+        SharedFabric(Rc::new(RefCell::new(fab)))
+    }
+
+    /// install routes the fabric calls of the current thread to the fabric, see [install_host]
+    pub fn install(&self) -> HostGuard {
+        install_host(self.clone())
+    }
+
+    pub fn borrow(&self) -> Ref<'_, MockFabric> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, MockFabric> {
+        self.0.borrow_mut()
+    }
+}
+
+impl MockHost for SharedFabric {
+    fn host_call(&mut self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult {
+        self.0.borrow_mut().host_call(binding, ns, op, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(mf: &mut MockFabric, ns: &str, op: &str, params: Value) -> Value {
+        let req =
+            json!({ "jpc": "1.0", "id": "test", "module": ns, "method": op, "params": params });
+        let res = mf
+            .host_call("test", ns, op, &serde_json::to_vec(&req).unwrap())
+            .unwrap();
+        serde_json::from_slice(&res).unwrap()
+    }
+
+    /// fixture is the fabric of the tests, a context object and a second object having a file of the same
+    /// path
+    fn fixture() -> MockFabric {
+        let fab: RootMockFabric = serde_json::from_value(json!({
+            "library": { "id": "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ", "objects": [
+                {
+                    "id": "iq__4MyXwriZzDG25deHUFUNCDkmi6Az",
+                    "hash": "hq__9G79fi7NKap3TD29EZSFGJbZTpaK8cYXPdUk5TkMkxZGg8E8B5A3QfdmzRcjyZHLBYdDH4RMWn",
+                    "meta": { "a": { "b": [1, 2] } },
+                    "parts": [{ "data": base64::encode(b"part data") }],
+                    "files": { "assets/x.txt": { "mime": "text/plain", "data": base64::encode(b"file data") } },
+                },
+                {
+                    "id": "iq__39vwVPNjczBWa5LEnKGRd9bWQN9J",
+                    "files": { "assets/x.txt": { "data": base64::encode(b"other") } },
+                },
+            ]},
+            "call": { "qinfo": { "id": "iq__4MyXwriZzDG25deHUFUNCDkmi6Az" } },
+        }))
+        .unwrap();
+        MockFabric::from_fixture(fab, Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn test_meta_and_errors() {
+        let mut mf = fixture();
+        call(
+            &mut mf,
+            "core",
+            "SQMDSet",
+            json!({ "path": "/c/d", "meta": "x" }),
+        );
+        assert_eq!(
+            call(&mut mf, "core", "SQMDGet", json!({ "path": "/c" }))["result"],
+            json!({ "d": "x" })
+        );
+        call(
+            &mut mf,
+            "core",
+            "SQMDMerge",
+            json!({ "path": "/c", "meta": "{\"e\" : 1}" }),
+        );
+        assert_eq!(
+            call(&mut mf, "core", "SQMDQuery", json!({ "query": "$['c'].e" }))["result"],
+            json!(1)
+        );
+        assert_eq!(
+            call(&mut mf, "core", "SQMDQuery", json!({ "query": "$.a.b[1]" }))["result"],
+            json!(2)
+        );
+        call(&mut mf, "core", "SQMDDelete", json!({ "path": "/c/d" }));
+        let missing = call(&mut mf, "core", "SQMDGet", json!({ "path": "/c/d" }));
+        assert_eq!(missing["error"]["kind"], "NotExist");
+    }
+
+    #[test]
+    fn test_content_lifecycle() {
+        let mut mf = fixture();
+        let sid = call(&mut mf, "ctx", "NewStream", json!({}))["result"]["stream_id"].clone();
+        let sid = sid.as_str().unwrap();
+        let w: Value =
+            serde_json::from_slice(&mf.host_call("test", sid, "Write", b"hello").unwrap()).unwrap();
+        assert_eq!(w["written"], 5);
+
+        let qwt = call(&mut mf, "core", "QModifyContent", json!({}))["result"]["qwtoken"].clone();
+        assert!(qwt.as_str().unwrap().starts_with("tqw__"));
+        let cp = call(
+            &mut mf,
+            "core",
+            "QCreatePartFromStream",
+            json!({ "qwtoken": qwt, "stream_id": sid }),
+        );
+        assert_eq!(cp["result"]["size"], 5);
+        let fin = call(
+            &mut mf,
+            "core",
+            "QFinalizeContent",
+            json!({ "qwtoken": qwt }),
+        );
+        let qhash = fin["result"]["qhash"].as_str().unwrap().to_string();
+        let oh: ObjectHash = qhash.parse().unwrap();
+        assert_eq!(oh.size, 14);
+
+        let versions = call(
+            &mut mf,
+            "core",
+            "QGetVersions",
+            json!({ "qid": "iq__4MyXwriZzDG25deHUFUNCDkmi6Az" }),
+        );
+        assert_eq!(versions["result"]["versions"].as_array().unwrap().len(), 2);
+
+        let out = call(&mut mf, "ctx", "NewStream", json!({}))["result"]["stream_id"].clone();
+        let qphash = cp["result"]["qphash"].clone();
+        call(
+            &mut mf,
+            "core",
+            "QWritePartToStream",
+            json!({ "stream_id": out, "qphash": qphash, "qihot": qhash, "off": 1, "len": 3 }),
+        );
+        let read = mf
+            .host_call("test", out.as_str().unwrap(), "Reader", br#"{"len" : 10}"#)
+            .unwrap();
+        assert_eq!(read, b"ell");
+
+        call(
+            &mut mf,
+            "core",
+            "QFileToStream",
+            json!({ "stream_id": FOS, "path": "assets/x.txt", "hash_or_token": "" }),
+        );
+        assert_eq!(mf.output(), b"file data");
+    }
+
+    #[test]
+    fn test_modify_and_commit() {
+        let mut mf = fixture();
+        let qwt = call(
+            &mut mf,
+            "core",
+            "QModifyContent",
+            json!({ "qtype": "hq__type", "meta": { "a": { "c": 3 } } }),
+        )["result"]["qwtoken"]
+            .clone();
+        let o = &mf.fab.library.objects[0];
+        assert_eq!(o.qtype, "hq__type");
+        assert_eq!(o.meta, json!({ "a": { "b": [1, 2], "c": 3 } }));
+        let fin = call(
+            &mut mf,
+            "core",
+            "QFinalizeContent",
+            json!({ "qwtoken": qwt }),
+        );
+        let commit = call(
+            &mut mf,
+            "core",
+            "QFinalizeContent",
+            json!({ "qhash": fin["result"]["qhash"] }),
+        );
+        assert_eq!(commit["result"], fin["result"]);
+        let unknown = call(
+            &mut mf,
+            "core",
+            "QFinalizeContent",
+            json!({ "qhash": "hq__unknown" }),
+        );
+        assert_eq!(unknown["error"]["kind"], "NotExist");
+    }
+
+    #[test]
+    fn test_ext_defaults() {
+        let mut mf = fixture();
+        let proxy = call(
+            &mut mf,
+            "ext",
+            "ProxyHttp",
+            json!({ "request": { "url": "https://example.com" } }),
+        );
+        assert_eq!(proxy["result"]["status"], 200);
+        assert_eq!(mf.ext_calls[0].1["request"]["url"], "https://example.com");
+
+        let hash = mf.fab.library.objects[0].hash.clone();
+        let code = mf.fab.library.objects[0].parts[0].hash.clone();
+        let req = json!({ "module": "", "function": "f", "params": {}, "object_hash": hash, "code_part_hash": code });
+        let res: Value = serde_json::from_slice(
+            &mf.host_call(
+                "test",
+                "ctx",
+                "CallExternalBitcode",
+                &serde_json::to_vec(&req).unwrap(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res["result"]["function_return"], Value::Null);
+        let req = json!({ "object_hash": hash, "code_part_hash": "hqp_missing" });
+        let res: Value = serde_json::from_slice(
+            &mf.host_call(
+                "test",
+                "ctx",
+                "CallExternalBitcode",
+                &serde_json::to_vec(&req).unwrap(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res["error"]["kind"], "NotExist");
+        assert_eq!(mf.ext_calls.len(), 2);
+
+        mf.fab
+            .responses
+            .insert("ProxyHttp".to_string(), json!("canned"));
+        assert_eq!(
+            call(&mut mf, "ext", "ProxyHttp", json!({}))["result"],
+            "canned"
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let mut mf = fixture();
+        let body = |v: Value| v["result"]["http"]["body"].clone();
+        let dir = body(call(&mut mf, "search", "NewIndexBuilder", json!({})))["dir"].clone();
+        for (name, id) in [("title", 0), ("body", 1)] {
+            let f = call(
+                &mut mf,
+                "search",
+                "BuilderAddTextField",
+                json!({ "field_name": name, "type": 2, "stored": true }),
+            );
+            assert_eq!(body(f)["field"], id);
+        }
+        for (title, text) in [("The Old Man and the Sea", "a fish"), ("Birds", "robin")] {
+            let doc = body(call(&mut mf, "search", "DocumentCreate", json!({})))
+                ["document-create-id"]
+                .clone();
+            call(
+                &mut mf,
+                "search",
+                "DocumentAddText",
+                json!({ "field": 0, "value": title, "doc_id": doc }),
+            );
+            call(
+                &mut mf,
+                "search",
+                "DocumentAddText",
+                json!({ "field": "body", "value": text, "doc": doc }),
+            );
+            call(
+                &mut mf,
+                "search",
+                "IndexWriterAddDocument",
+                json!({ "document_id": doc }),
+            );
+        }
+        call(&mut mf, "search", "IndexWriterCommit", json!({}));
+        let archived = body(call(
+            &mut mf,
+            "search",
+            "ArchiveIndexToPart",
+            json!({ "directory": dir }),
+        ));
+        let hash = mf.fab.library.objects[0].hash.clone();
+
+        call(&mut mf, "search", "NewIndexBuilder", json!({}));
+        call(
+            &mut mf,
+            "search",
+            "RestoreIndexFromPart",
+            json!({ "content-hash": hash, "part-hash": archived["qphash"] }),
+        );
+        call(
+            &mut mf,
+            "search",
+            "QueryParserForIndex",
+            json!({ "fields": ["title", "body"] }),
+        );
+        call(
+            &mut mf,
+            "search",
+            "QueryParserParseQuery",
+            json!({ "query": "sea" }),
+        );
+        let found = body(call(&mut mf, "search", "QueryParserSearch", json!({})));
+        assert_eq!(
+            found,
+            json!([{ "title": ["The Old Man and the Sea"], "body": ["a fish"] }])
+        );
+        let unknown = call(&mut mf, "search", "Unknown", json!({}));
+        assert_eq!(unknown["error"]["kind"], "NotImplemented");
+    }
+
+    #[test]
+    fn test_qfile_to_stream_version() {
+        let fab = SharedFabric::new(fixture());
+        let _guard = fab.install();
+        let other: elvwasm::HashOrToken = fab.borrow().fab.library.objects[1].hash.parse().unwrap();
+        let bcc = elvwasm::BitcodeContext::default();
+        let res = bcc
+            .write_qfile_to_stream_typed(FOS.to_string(), "assets/x.txt".to_string(), &other)
+            .unwrap();
+        assert_eq!(res.written, 5);
+        assert_eq!(fab.borrow().output(), b"other");
+    }
+
+    #[test]
+    fn test_fixture_dir() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../samples/fixture");
+        let fab = SharedFabric::new(MockFabric::load(&dir).unwrap());
+        let _guard = fab.install();
+        let qid: elvwasm::QId = "iq__4MyXwriZzDG25deHUFUNCDkmi6Az".parse().unwrap();
+        let bcc = elvwasm::BitcodeContext::default();
+
+        let versions = bcc.q_get_versions_typed(&qid, false).unwrap();
+        assert_eq!(versions.versions.len(), 2);
+        let hash = versions.versions[1].hash.clone();
+        assert_eq!(hash.qid().unwrap(), qid);
+
+        let parts = bcc.q_part_list_typed(&hash.clone().into()).unwrap();
+        assert_eq!(parts.part_list.parts.len(), 2);
+        assert_eq!(parts.part_list.parts[0].size, 24);
+
+        let res = bcc
+            .q_file_to_stream_typed("fos", "assets/birds.txt", &hash.into())
+            .unwrap();
+        assert_eq!(res.mime_type, "text/plain; charset=utf-8");
+        assert_eq!(fab.borrow().output(), b"robin\nwren\nheron\n");
+        let meta = bcc.sqmd_get_json("/public/asset_metadata/title").unwrap();
+        assert_eq!(meta, b"\"Birds of the fabric\"");
+    }
+}
@@ -7,234 +7,289 @@ extern crate base64;
 extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
-extern crate json_dotpath;
-extern crate snailquote;
-use std::sync::{Arc};
-
-use elvwasm::ErrorKinds;
-use std::fs::File;
-use std::io::BufReader;
-use json_dotpath::DotPaths;
+#[macro_use]
+extern crate lazy_static;
+use std::sync::Mutex;
+
+mod bench;
+mod cassette;
+mod fabric;
+mod reload;
+mod request;
+mod search;
+mod serve;
+mod wasmer_provider;
+
+use cassette::{Entry, Recorder, Replayer};
+use fabric::{MockFabric, RootMockFabric};
+use reload::{describe_module, ModuleWatcher};
+use request::{build_request, CallArgs};
+use wasmer_provider::WasmerEngineProvider;
 use std::path::PathBuf;
 use structopt::StructOpt;
-use wasmer::{imports, Store, Universal};
-use wasmer_compiler_cranelift::Cranelift;
 use wasmtime_provider::WasmtimeEngineProvider;
 use wapc::WapcHost;
 
+/// Cassette selects whether host calls are answered by the mock fabric, recorded or replayed
+enum Cassette {
+    Off,
+    Record(Recorder),
+    Replay(Replayer),
+}
 
-use serde_derive::{Deserialize, Serialize};
-
-static mut QFAB: MockFabric = MockFabric{
-    fab : None
-};
+/// how often a watched wasm file is checked for changes
+pub(crate) const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct RootMockFabric {
-  pub library:Library,
-  pub call:serde_json::Value,
+lazy_static! {
+    pub(crate) static ref QFAB: Mutex<MockFabric> = Mutex::new(MockFabric::default());
+    static ref CASSETTE: Mutex<Cassette> = Mutex::new(Cassette::Off);
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Object {
-  pub hash: String,
-  pub id: String,
-  pub qlib_id: String,
-  #[serde(rename = "type")]
-  pub qtype: String,
-  pub write_token: String,
-  pub meta : serde_json::Map<String, serde_json::Value>
+/// host_callback dispatches every waPC host call from the bitcode to the mock fabric
+pub fn host_callback(i_cb:u64, id:&str, context:&str, method:&str, pkg:&[u8])-> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
+    println!("In host callback, values i_cb = {} id = {} method = {} context = {}, pkg len = {}", i_cb, id, method, context, pkg.len());
+    let mut cassette = CASSETTE.lock().map_err(|e| e.to_string())?;
+    if let Cassette::Replay(r) = &mut *cassette {
+        return r.host_call(id, context, method, pkg);
+    }
+    let res = QFAB.lock().map_err(|e| e.to_string())?.host_call(id, context, method, pkg);
+    if let Cassette::Record(r) = &mut *cassette {
+        r.record(&Entry::new(id, context, method, pkg, &res))?;
+    }
+    res
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Library {
-  pub id: String,
-  pub objects: std::vec::Vec<Object>,
-}
+#[derive(StructOpt, Clone)]
+enum Command {
+    /// Run the call block of a fabric fixture
+    Run {
+        /// Input wasm
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
 
-#[derive(Serialize, Deserialize,  Clone, Debug)]
-pub struct MockFabric{
-    fab : Option<RootMockFabric>
-}
+        /// Fabric fixture file or directory with a call block
+        #[structopt(parse(from_os_str))]
+        fabric: PathBuf,
+    },
+    /// Call a handler with a request built from flags
+    Call {
+        /// Input wasm
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct JPCRequest {
-  pub jpc: String,
-  pub params: serde_json::Map<String, serde_json::Value>
-}
+        /// Fabric fixture file or directory providing the content of the call, an empty library if absent
+        #[structopt(long, parse(from_os_str))]
+        fabric: Option<PathBuf>,
 
-impl MockFabric{
-    pub fn init(& mut self, path_to_json:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
-        let file = File::open(path_to_json)?;
-        let reader = BufReader::new(file);
+        /// Handler method name
+        #[structopt(long)]
+        method: String,
 
-        // Read the JSON contents of the file as an instance of `User`.
-        let json_rep:RootMockFabric = serde_json::from_reader(reader)?;
-        self.fab = Some(json_rep);
-        return Ok("DONE".as_bytes().to_vec())
-    }
-    pub fn write_stream(&self, _json_rep:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        println!("in WriteStream");
-        Ok("Not Implemented".as_bytes().to_vec())
-    }
-    pub fn sqmd_delete(&self, json_rep:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        println!("in SQMD delete");
-        let j:JPCRequest = serde_json::from_str(json_rep)?;
-        let path = j.params["path"].to_string();
-        if  !path.is_empty(){
-            let mut fab = self.fab.clone().unwrap();
-            let p = &snailquote::unescape(&path).unwrap();
-            let pp:String = p.chars().map(|x| match x {
-                '/' => '.',
-                _ => x
-            }).collect();
-            fab.library.objects[0].meta.dot_remove(&pp[1..])?;//{
-            return Ok("DONE".as_bytes().to_vec())
-        }else{
-            println!("failed to find path argument");
-        }
-        Ok("FAILED".as_bytes().to_vec())
-    }
-    pub fn sqmd_set(&self, json_rep:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        println!("in SQMD set");
-        let j:JPCRequest = serde_json::from_str(json_rep)?;
-        let path = j.params["path"].to_string();
-        let meta = j.params["meta"].to_string();
-        if !path.is_empty(){
-            let mut fab = self.fab.clone().unwrap();
-            let p = &snailquote::unescape(&path).unwrap();
-            let pp:String = p.chars().map(|x| match x {
-                '/' => '.',
-                _ => x
-            }).collect();
-            fab.library.objects[0].meta.dot_set(&pp[1..], meta)?;
-            return Ok("DONE".as_bytes().to_vec())
-
-        }else{
-            println!("failed to find path argument");
-        }
-        Ok("FAILED".as_bytes().to_vec())
-    }
-    pub fn sqmd_get(&self, json_rep:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        println!("in SQMD get");
-        let j:JPCRequest = serde_json::from_str(json_rep)?;
-        let path = j.params["path"].to_string();
-        if !path.is_empty(){
-            let fab = self.fab.clone().unwrap();
-            let p = &snailquote::unescape(&path).unwrap();
-            let pp:String = p.chars().map(|x| match x {
-                '/' => '.',
-                _ => x
-            }).collect();
-            let gotten:Option<serde_json::Value> = fab.library.objects[0].meta.dot_get(&pp[1..])?;
-            let ret = gotten.unwrap();
-            println!("sqmd_get returning = {}", ret);
-            return Ok(ret.to_string().as_bytes().to_vec())
-        }else{
-            println!("failed to find path argument");
-        }
-        Ok("FAILED".as_bytes().to_vec())
-    }
-    pub fn proxy_http(&self, _json_rep:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        println!("in ProxyHttp");
-        let to_encode = r#"{"url" : {"type" : "application/json"}} "#.as_bytes();
-        let enc = base64::encode(to_encode);
-        Ok(format!(r#"{{"result": "{}"}}"#, enc).as_bytes().to_vec())
-    }
-    pub fn callback(&self, _json_rep:&str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        println!("in callback");
-        let to_encode = r#"{"url" : {"type" : "application/json"}} "#.as_bytes();
-        let enc = base64::encode(to_encode);
-        Ok(format!(r#"{{"result": "{}"}}"#, enc).as_bytes().to_vec())
-    }
-    pub fn host_callback(i_cb:u64, id:&str, context:&str, method:&str, pkg:&[u8])-> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>{
-        let s_pkg = std::str::from_utf8(pkg)?;
-        println!("In host callback, values i_cb = {} id = {} method = {} context = {}, pkg = {}", i_cb, id,method,context, s_pkg);
-        match method {
-            "SQMDGet" =>{
-               unsafe{ QFAB.sqmd_get(s_pkg) }
-            }
-            "SQMDSet" =>{
-                unsafe{ QFAB.sqmd_set(s_pkg) }
-             }
-            "SQMDDelete" =>{
-                unsafe{ QFAB.sqmd_delete(s_pkg) }
-             }
-            "Write" => {
-                unsafe{ QFAB.write_stream(s_pkg) }
-            }
-            "Callback" => {
-                unsafe{ QFAB.callback(s_pkg) }
-            }
-            "ProxyHttp" => {
-                unsafe{ QFAB.proxy_http(s_pkg) }
-            }
-            _ => {
-                Err(Box::new(ErrorKinds::NotExist("Method not handled")))
-            }
-        }
-    }
-}
+        /// Request path, defaults to /<method>
+        #[structopt(long)]
+        path: Option<String>,
+
+        /// Http verb
+        #[structopt(long, default_value = "GET")]
+        verb: String,
+
+        /// Query parameter as name=value, may be repeated
+        #[structopt(long = "query", number_of_values = 1)]
+        query: Vec<String>,
+
+        /// Header as "Name: value", may be repeated
+        #[structopt(long = "header", number_of_values = 1)]
+        headers: Vec<String>,
+
+        /// Request body as json or text, @path reads it from a file
+        #[structopt(long)]
+        body: Option<String>,
 
-struct WasmerHolder{
-    _instance:wasmer::Instance
+        /// Request id
+        #[structopt(long, default_value = "mockcall")]
+        id: String,
+    },
+    /// Serve the bitcode's handlers over http
+    Serve {
+        /// Input wasm
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Fabric fixture file or directory providing the content, an empty library if absent
+        #[structopt(long, parse(from_os_str))]
+        fabric: Option<PathBuf>,
+
+        /// Address to listen on
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+
+        /// Handler method for every request, defaults to the first segment of the request path
+        #[structopt(long)]
+        method: Option<String>,
+    },
+    /// Count the host calls of the objtar workload for each way of writing the output stream
+    Bench {
+        /// Fabric fixture file or directory providing the content, an empty library if absent
+        #[structopt(long, parse(from_os_str))]
+        fabric: Option<PathBuf>,
+
+        /// Parts added to the content object before the runs
+        #[structopt(long, default_value = "16")]
+        parts: usize,
+
+        /// Size in bytes of each added part
+        #[structopt(long, default_value = "1048576")]
+        part_size: usize,
+
+        /// Bytes per host write of the buffered and batched writers
+        #[structopt(long, default_value = "1048576")]
+        batch: usize,
+    },
 }
 
-impl wapc::WebAssemblyEngineProvider for WasmerHolder{
-    fn init(&mut self, _host: Arc<wapc::ModuleState>) -> std::result::Result<(), Box<dyn std::error::Error>>{
-        Ok(())
-    }
-    fn call(&mut self, _op_length: i32, _msg_length: i32) -> std::result::Result<i32, Box<dyn std::error::Error>>{
-        //.instance.store().engine.
-        Ok(0)
-    }
-    fn replace(&mut self, _bytes: &[u8]) -> std::result::Result<(), Box<dyn std::error::Error>>{
-        Ok(())
+fn load_fabric(fabric: &Option<PathBuf>) -> Result<MockFabric, Box<dyn std::error::Error + Send + Sync>> {
+    match fabric {
+        Some(f) => MockFabric::load(f),
+        None => MockFabric::from_fixture(RootMockFabric::default(), std::path::Path::new(".")),
     }
 }
 
 #[derive(StructOpt)]
-#[structopt(name = "example", about = "An example of StructOpt usage.")]
+#[structopt(name = "mock", about = "Runs bitcode against an in-process mock fabric")]
 struct Opt {
-    /// Activate debug mode
-    // short and long flags (-w, --wasmer) will be deduced from the field's name
-    #[structopt(short, long)]
+    /// Engine running the bitcode, wasmer or wasmtime
+    #[structopt(short, long, default_value = "wasmtime")]
     mode: String,
 
-    /// Input wasm
-    #[structopt(parse(from_os_str))]
-    input: PathBuf,
+    /// Record every host call to a JSONL cassette
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+
+    /// Answer host calls from a JSONL cassette, failing on calls that differ from the recording
+    #[structopt(long, parse(from_os_str), conflicts_with = "record")]
+    replay: Option<PathBuf>,
 
+    /// Watch the wasm file and reload the module when it changes, keeping the fabric content.  Run and
+    /// call repeat the call after each reload, recording or replaying the cassette from its start
+    #[structopt(short, long)]
+    watch: bool,
 
-    /// Input fabric/call
-    #[structopt(parse(from_os_str))]
-    fabric: PathBuf,
+    /// Save the output stream (fos) of the call to a file
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
 
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+/// print_callback reports the http status and headers the bitcode set up with Callback
+fn print_callback(cb: &serde_json::Value) {
+    let http = &cb["http"];
+    println!("callback status = {}", http["status"]);
+    if let Some(headers) = http["headers"].as_object() {
+        for (k, v) in headers {
+            let values: Vec<String> = match v.as_array() {
+                Some(a) => a.iter().map(|s| s.as_str().map(String::from).unwrap_or_else(|| s.to_string())).collect(),
+                None => vec![v.to_string()],
+            };
+            println!("  {}: {}", k, values.join(", "));
+        }
+    }
 }
 
 pub fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("In main");
     let opt = Opt::from_args();
-    unsafe{QFAB.init(&opt.fabric.into_os_string().into_string().unwrap())?;}
-    let module_wat = std::fs::read(&opt.input.into_os_string().into_string().unwrap())?;
+    let mut serve_at = None;
+    let (input, fab, call) = match opt.cmd.clone() {
+        Command::Run { input, fabric } => {
+            let fab = MockFabric::load(&fabric)?;
+            let call = serde_json::to_vec(fab.request())?;
+            (input, fab, call)
+        }
+        Command::Serve { input, fabric, addr, method } => {
+            serve_at = Some((addr, method));
+            (input, load_fabric(&fabric)?, Vec::new())
+        }
+        Command::Call { input, fabric, method, path, verb, query, headers, body, id } => {
+            let fab = load_fabric(&fabric)?;
+            let args = CallArgs {
+                path: path.unwrap_or_else(|| format!("/{method}")),
+                id, method, verb, query, headers, body,
+            };
+            let call = serde_json::to_vec(&build_request(&args, &fab)?)?;
+            (input, fab, call)
+        }
+        Command::Bench { fabric, parts, part_size, batch } => {
+            let results = bench::bench(load_fabric(&fabric)?, parts, part_size, batch)?;
+            bench::print(&results);
+            return Ok(());
+        }
+    };
+    *QFAB.lock().map_err(|e| e.to_string())? = fab;
+    *CASSETTE.lock().map_err(|e| e.to_string())? = open_cassette(&opt)?;
+    let module_wat = std::fs::read(&input)?;
     let h;
     if opt.mode == "wasmer"{
-        let compiler = Cranelift::new();
-        // Put it into an engine and add it to the store
-        let store = Store::new(&Universal::new(compiler).engine());
-        let wasmer_mod = wasmer::Module::new(&store, &module_wat)?;
-        let import_object = imports! {};
-        let instance = wasmer::Instance::new(&wasmer_mod, &import_object)?;
-        let wasm_holder = WasmerHolder{_instance:instance};
-        let host = wapc::WapcHost::new(Box::new(wasm_holder), MockFabric::host_callback)?;
+        let engine = WasmerEngineProvider::new(&module_wat)?;
+        let host = WapcHost::new(Box::new(engine), host_callback)?;
         h = Some(host);
     }else{
         let engine = WasmtimeEngineProvider::new(&module_wat, None);
-        let host = WapcHost::new(Box::new(engine), MockFabric::host_callback)?;
+        let host = WapcHost::new(Box::new(engine), host_callback)?;
         h = Some(host)
     }
 
-    h.unwrap().call("_JPC", &serde_json::to_vec(unsafe{&QFAB.fab.clone().unwrap().call}).unwrap())?;
+    let h = h.unwrap();
+    println!("loaded {} {}", input.display(), describe_module(&h, &module_wat));
+    let mut watcher = if opt.watch { Some(ModuleWatcher::new(&input)) } else { None };
+    if let Some((addr, method)) = serve_at {
+        return serve::serve(&h, &addr, method.as_deref(), watcher.as_mut());
+    }
+    run_call(&h, &call, &opt)?;
+    if let Some(w) = watcher.as_mut() {
+        println!("watching {} for changes", input.display());
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            if w.reload_if_changed(&h)? {
+                QFAB.lock().map_err(|e| e.to_string())?.reset_call();
+                // the previous run consumed the replayed cassette, each run starts from its beginning
+                *CASSETTE.lock().map_err(|e| e.to_string())? = open_cassette(&opt)?;
+                if let Err(e) = run_call(&h, &call, &opt) {
+                    println!("call failed: {e}");
+                }
+            }
+        }
+    }
     Ok(())
 }
 
+/// open_cassette creates the recording or loads the replayed cassette selected by the options
+fn open_cassette(opt: &Opt) -> Result<Cassette, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(match (&opt.record, &opt.replay) {
+        (Some(path), _) => Cassette::Record(Recorder::create(path)?),
+        (_, Some(path)) => Cassette::Replay(Replayer::load(path)?),
+        _ => Cassette::Off,
+    })
+}
+
+/// run_call makes the bitcode call and reports its result, callback and output stream
+fn run_call(h: &WapcHost, call: &[u8], opt: &Opt) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let res = h.call("_JPC", call);
+    if let Cassette::Replay(r) = &*CASSETTE.lock().map_err(|e| e.to_string())? {
+        r.finish().map_err(|e| format!("replay failed:\n{e}"))?;
+        println!("replay matched the cassette");
+    }
+    let res = res?;
+    println!("result = {}", String::from_utf8_lossy(&res));
+    let fab = QFAB.lock().map_err(|e| e.to_string())?;
+    if let Some(cb) = &fab.callback {
+        print_callback(cb);
+    }
+    println!("output stream size = {}", fab.output().len());
+    if let Some(path) = &opt.output {
+        std::fs::write(path, fab.output())?;
+        println!("output stream saved to {}", path.display());
+    }
+    Ok(())
+}
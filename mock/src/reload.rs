@@ -0,0 +1,70 @@
+//! Hot reload of the bitcode module.  [ModuleWatcher] polls the wasm file and swaps a changed module into
+//! the running waPC host, the mock fabric and its content are left untouched.
+
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use wapc::WapcHost;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// describe_module reports the build id (sha256 of the wasm) and the elvwasm library version of a module
+pub fn describe_module(host: &WapcHost, wasm: &[u8]) -> String {
+    let build_id: String = Sha256::digest(wasm)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    // _version is registered by implement_bitcode_module, modules built before it was added lack it
+    let version = match host.call("_version", &[]) {
+        Ok(v) => String::from_utf8_lossy(&v).to_string(),
+        Err(_) => "unknown".to_string(),
+    };
+    format!("build-id {build_id} library {version}")
+}
+
+pub struct ModuleWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl ModuleWatcher {
+    pub fn new(path: &Path) -> ModuleWatcher {
+        ModuleWatcher {
+            path: path.to_path_buf(),
+            modified: modified(path),
+        }
+    }
+
+    /// reload_if_changed replaces the host's module when the wasm file changed since the last check,
+    /// returning whether it did.  A module that fails to load, e.g. one still being written by the
+    /// compiler, is reported and the previous module kept.
+    pub fn reload_if_changed(&mut self, host: &WapcHost) -> BoxResult<bool> {
+        let m = modified(&self.path);
+        if m.is_none() || m == self.modified {
+            return Ok(false);
+        }
+        let wasm = std::fs::read(&self.path)?;
+        if wasm.is_empty() {
+            return Ok(false);
+        }
+        self.modified = m;
+        match host.replace_module(&wasm) {
+            Ok(()) => {
+                println!(
+                    "reloaded {} {}",
+                    self.path.display(),
+                    describe_module(host, &wasm)
+                );
+                Ok(true)
+            }
+            Err(e) => {
+                println!("failed to reload {}: {e}", self.path.display());
+                Ok(false)
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Builds the jpc [Request] sent to a bitcode module from the flags of `mock call` or an http request
+
+use crate::fabric::MockFabric;
+
+use elvwasm::{HttpParams, JpcParams, QInfo, Request};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// CallArgs are the http parts of a crafted request
+#[derive(Clone, Debug, Default)]
+pub struct CallArgs {
+    pub id: String,
+    pub method: String,
+    pub verb: String,
+    pub path: String,
+    /// `name=value`, repeated names accumulate
+    pub query: Vec<String>,
+    /// `Name: value` or `Name=value`, repeated names accumulate
+    pub headers: Vec<String>,
+    /// json or text, `@path` reads the body from a file
+    pub body: Option<String>,
+}
+
+fn split_pair<'a>(s: &'a str, seps: &[char]) -> BoxResult<(&'a str, &'a str)> {
+    let idx = s
+        .find(seps)
+        .ok_or_else(|| format!("expected name{}value, got {s}", seps[0]))?;
+    Ok((s[..idx].trim(), s[idx + 1..].trim()))
+}
+
+fn collect_pairs(items: &[String], seps: &[char]) -> BoxResult<HashMap<String, Vec<String>>> {
+    let mut m: HashMap<String, Vec<String>> = HashMap::new();
+    for item in items {
+        let (k, v) = split_pair(item, seps)?;
+        m.entry(k.to_string()).or_default().push(v.to_string());
+    }
+    Ok(m)
+}
+
+/// parse_body reads `@path` bodies from disk and decodes json bodies, anything else is sent as a string
+pub fn parse_body(body: &str) -> BoxResult<Value> {
+    let text = match body.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => body.to_string(),
+    };
+    Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)))
+}
+
+/// build_request assembles the request from the flags of `mock call`
+pub fn build_request(args: &CallArgs, fab: &MockFabric) -> BoxResult<Request> {
+    let body = match &args.body {
+        Some(b) => parse_body(b)?,
+        None => Value::Null,
+    };
+    let content_length = match &body {
+        Value::Null => 0,
+        Value::String(s) => s.len(),
+        v => v.to_string().len(),
+    };
+    let http = HttpParams {
+        headers: collect_pairs(&args.headers, &[':', '='])?,
+        path: args.path.clone(),
+        body,
+        query: collect_pairs(&args.query, &['='])?,
+        verb: args.verb.clone(),
+        content_length,
+        ..Default::default()
+    };
+    request_for(http, &args.id, &args.method, fab)
+}
+
+/// request_for wraps the http parameters into a request with the qinfo of the fabric's context object
+pub fn request_for(
+    http: HttpParams,
+    id: &str,
+    method: &str,
+    fab: &MockFabric,
+) -> BoxResult<Request> {
+    let o = fab
+        .fab
+        .library
+        .objects
+        .get(fab.current)
+        .cloned()
+        .unwrap_or_default();
+    let q_info: QInfo = serde_json::from_value(json!({
+        "hash": o.hash,
+        "id": o.id,
+        "qlib_id": o.qlib_id,
+        "type": o.qtype,
+        "write_token": o.write_token,
+    }))?;
+    Ok(Request {
+        id: id.to_string(),
+        jpc: "1.0".to_string(),
+        method: method.to_string(),
+        params: JpcParams { http },
+        q_info,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request() {
+        let args = CallArgs {
+            id: "call1".to_string(),
+            method: "image".to_string(),
+            verb: "GET".to_string(),
+            path: "/image/x".to_string(),
+            query: vec![
+                "height=200".to_string(),
+                "tag=a".to_string(),
+                "tag=b=c".to_string(),
+            ],
+            headers: vec!["Accept: image/png".to_string()],
+            body: Some(r#"{"crop" : true}"#.to_string()),
+        };
+        let req = build_request(&args, &MockFabric::default()).unwrap();
+        let http = &req.params.http;
+        assert_eq!(http.query["height"], vec!["200"]);
+        assert_eq!(http.query["tag"], vec!["a", "b=c"]);
+        assert_eq!(http.headers["Accept"], vec!["image/png"]);
+        assert_eq!(http.body["crop"], true);
+        assert!(parse_body("not json").unwrap().is_string());
+        assert!(collect_pairs(&["novalue".to_string()], &['=']).is_err());
+    }
+}
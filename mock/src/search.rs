@@ -0,0 +1,185 @@
+//! MockIndex stands in for the tantivy index the fabric builds for the `search` calls of elvwasm.  Documents
+//! are kept in memory, a query matches the documents having every query term in one of the query fields
+//! ignoring case, and an archived index is the JSON of its fields and documents.
+
+use elvwasm::ErrorKinds;
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+type OpResult = std::result::Result<Value, ErrorKinds>;
+
+/// the text of a document by field id
+type Doc = BTreeMap<u64, Vec<String>>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MockIndex {
+    /// the directory returned by NewIndexBuilder
+    pub dir: String,
+    /// field names, the position is the field id
+    pub fields: Vec<String>,
+    /// the committed documents
+    pub docs: Vec<Doc>,
+    /// documents by document-create-id
+    #[serde(skip)]
+    created: Vec<Doc>,
+    /// documents added to the writer and not yet committed
+    #[serde(skip)]
+    staged: Vec<Doc>,
+    #[serde(skip)]
+    query_fields: Vec<u64>,
+    #[serde(skip)]
+    terms: Vec<String>,
+}
+
+fn invalid(msg: String) -> ErrorKinds {
+    ErrorKinds::Invalid(msg)
+}
+
+impl MockIndex {
+    pub fn new(dir: String) -> MockIndex {
+        MockIndex {
+            dir,
+            ..Default::default()
+        }
+    }
+
+    /// restore reads an index archived by [MockIndex::archive]
+    pub fn restore(data: &[u8]) -> std::result::Result<MockIndex, ErrorKinds> {
+        serde_json::from_slice(data)
+            .map_err(|e| invalid(format!("part is not an index archived by the mock: {e}")))
+    }
+
+    pub fn archive(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    /// field resolves a field given by id or by name
+    fn field(&self, v: Option<&Value>) -> std::result::Result<u64, ErrorKinds> {
+        let id = match v {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => self.fields.iter().position(|f| f == s).map(|i| i as u64),
+            _ => None,
+        };
+        id.filter(|i| (*i as usize) < self.fields.len())
+            .ok_or_else(|| invalid(format!("unknown field {}", v.cloned().unwrap_or_default())))
+    }
+
+    fn doc_id(p: &Value) -> u64 {
+        ["doc_id", "doc", "document_id"]
+            .iter()
+            .find_map(|k| p.get(*k).and_then(|v| v.as_u64()))
+            .unwrap_or_default()
+    }
+
+    fn created(&self, p: &Value) -> std::result::Result<usize, ErrorKinds> {
+        let id = Self::doc_id(p) as usize;
+        if id >= self.created.len() {
+            return Err(ErrorKinds::NotExist(format!("document {id}")));
+        }
+        Ok(id)
+    }
+
+    fn matches(&self, doc: &Doc) -> bool {
+        self.terms.iter().all(|t| {
+            self.query_fields.iter().any(|f| {
+                doc.get(f)
+                    .map(|texts| texts.iter().any(|s| s.to_lowercase().contains(t)))
+                    .unwrap_or_default()
+            })
+        })
+    }
+
+    fn named(&self, doc: &Doc) -> Value {
+        let m: Map<String, Value> = doc
+            .iter()
+            .map(|(f, texts)| (self.fields[*f as usize].clone(), json!(texts)))
+            .collect();
+        Value::Object(m)
+    }
+
+    /// call answers the search operations acting on the index alone, returning the body of the reply
+    pub fn call(&mut self, op: &str, p: &Value) -> OpResult {
+        match op {
+            "BuilderAddTextField" => {
+                let name = match p.get("field_name") {
+                    Some(n) => n,
+                    None => p.get("name").unwrap_or(&Value::Null),
+                };
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| invalid("text field without a name".to_string()))?;
+                // a restored index is given its fields again
+                let id = match self.fields.iter().position(|f| f == name) {
+                    Some(i) => i,
+                    None => {
+                        self.fields.push(name.to_string());
+                        self.fields.len() - 1
+                    }
+                };
+                Ok(json!({ "field": id }))
+            }
+            "DocumentCreate" => {
+                self.created.push(Doc::new());
+                Ok(json!({ "document-create-id": self.created.len() - 1 }))
+            }
+            "DocumentAddText" => {
+                let field = self.field(p.get("field"))?;
+                let id = self.created(p)?;
+                let text = match p.get("value") {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(Value::Null) | None => String::new(),
+                    Some(v) => v.to_string(),
+                };
+                self.created[id].entry(field).or_default().push(text);
+                Ok(json!({}))
+            }
+            "IndexWriterAddDocument" => {
+                let id = self.created(p)?;
+                self.staged.push(self.created[id].clone());
+                Ok(json!({ "opstamp": self.docs.len() + self.staged.len() }))
+            }
+            "IndexWriterCommit" => {
+                self.docs.append(&mut self.staged);
+                Ok(json!({ "opstamp": self.docs.len() }))
+            }
+            "QueryParserForIndex" => {
+                let fields = match p.get("fields") {
+                    Some(Value::Array(a)) => a,
+                    _ => return Err(invalid("query parser without fields".to_string())),
+                };
+                self.query_fields = fields
+                    .iter()
+                    .map(|f| self.field(Some(f)))
+                    .collect::<std::result::Result<_, _>>()?;
+                Ok(json!({}))
+            }
+            "QueryParserParseQuery" => {
+                let query = p.get("query").and_then(|q| q.as_str()).unwrap_or_default();
+                self.terms = query.split_whitespace().map(|t| t.to_lowercase()).collect();
+                Ok(json!({}))
+            }
+            "QueryParserSearch" => {
+                let docs: Vec<Value> = self
+                    .docs
+                    .iter()
+                    .filter(|d| self.matches(d))
+                    .map(|d| self.named(d))
+                    .collect();
+                Ok(json!(docs))
+            }
+            // the steps of building and reading a tantivy index have nothing to do in memory
+            "BuilderBuild"
+            | "BuilderCreateIndex"
+            | "DocumentCreateIndex"
+            | "IndexCreateWriter"
+            | "IndexReaderBuilderCreate"
+            | "IndexReaderSearcher"
+            | "ReaderBuilderQueryParserCreate" => Ok(json!({})),
+            _ => Err(ErrorKinds::NotImplemented(format!(
+                "{op} is not implemented by the mock fabric"
+            ))),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! A local http server answering each request by calling the bitcode module as the fabric would.
+//!
+//! Every request is converted into the jpc [elvwasm::Request] the fabric sends, with the headers, query,
+//! body, client ip and self url of the request.  The bitcode method is the first segment of the path
+//! unless one is given on the command line.  The response is the status and headers the bitcode set up
+//! with a Callback and the bytes it wrote to the output stream, or the jpc reply of the call when it made
+//! no Callback.  Requests are served one at a time against the same mock fabric so content created by one
+//! request is visible to the next.  With `--watch` a rebuilt module is swapped in between requests.
+
+use crate::reload::ModuleWatcher;
+use crate::request::request_for;
+use crate::{QFAB, WATCH_INTERVAL};
+
+use elvwasm::HttpParams;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use tiny_http::{Header, Response, Server};
+use wapc::WapcHost;
+
+type BoxResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// url_decode decodes the percent escapes and `+` of a query component
+fn url_decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < b.len() => {
+                let hex = std::str::from_utf8(&b[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(c) => {
+                        out.push(c);
+                        i += 2;
+                    }
+                    Err(_) => out.push(b'%'),
+                }
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// parse_query splits a query string into its decoded values by name
+pub fn parse_query(q: &str) -> HashMap<String, Vec<String>> {
+    let mut m: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in q.split('&').filter(|p| !p.is_empty()) {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        m.entry(url_decode(k)).or_default().push(url_decode(v));
+    }
+    m
+}
+
+/// method_for returns the first segment of the path e.g. `image` for `/image/x`
+pub fn method_for(path: &str) -> String {
+    path.trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn to_http_params(req: &mut tiny_http::Request) -> BoxResult<HttpParams> {
+    let (path, query) = match req.url().split_once('?') {
+        Some((p, q)) => (p.to_string(), parse_query(q)),
+        None => (req.url().to_string(), HashMap::new()),
+    };
+    let mut headers: HashMap<String, Vec<String>> = HashMap::new();
+    for h in req.headers() {
+        headers
+            .entry(h.field.as_str().to_string())
+            .or_default()
+            .push(h.value.as_str().to_string());
+    }
+    let host = headers
+        .get("Host")
+        .and_then(|h| h.first())
+        .cloned()
+        .unwrap_or_default();
+    let mut raw = Vec::new();
+    req.as_reader().read_to_end(&mut raw)?;
+    let body = if raw.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&raw)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&raw).to_string()))
+    };
+    let version = req.http_version();
+    Ok(HttpParams {
+        self_url: format!("http://{host}{path}"),
+        path,
+        query,
+        headers,
+        body,
+        verb: req.method().to_string(),
+        content_length: raw.len(),
+        client_ip: req
+            .remote_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or_default(),
+        proto: format!("HTTP/{}.{}", version.0, version.1),
+        host,
+        ..Default::default()
+    })
+}
+
+fn header(name: &str, value: &str) -> Option<Header> {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).ok()
+}
+
+/// respond builds the http response from the callback and output stream or from the jpc reply
+fn respond(reply: BoxResult<Vec<u8>>) -> Response<std::io::Cursor<Vec<u8>>> {
+    let reply = match reply {
+        Ok(r) => r,
+        Err(e) => return Response::from_string(e.to_string()).with_status_code(500),
+    };
+    let fab = match QFAB.lock() {
+        Ok(f) => f,
+        Err(e) => return Response::from_string(e.to_string()).with_status_code(500),
+    };
+    if let Some(cb) = &fab.callback {
+        let http = &cb["http"];
+        let status = http["status"].as_u64().unwrap_or(200) as u16;
+        let mut resp = Response::from_data(fab.output().to_vec()).with_status_code(status);
+        if let Some(hs) = http["headers"].as_object() {
+            for (k, vs) in hs {
+                // the length is set by the server from the output stream
+                if k.eq_ignore_ascii_case("Content-Length") {
+                    continue;
+                }
+                for v in vs.as_array().cloned().unwrap_or_default() {
+                    if let Some(h) = header(k, v.as_str().unwrap_or_default()) {
+                        resp.add_header(h);
+                    }
+                }
+            }
+        }
+        return resp;
+    }
+    let v: Value = serde_json::from_slice(&reply).unwrap_or_default();
+    let status = v["error"]["status"].as_u64().unwrap_or(200) as u16;
+    let mut resp = Response::from_data(reply).with_status_code(status);
+    if let Some(h) = header("Content-Type", "application/json") {
+        resp.add_header(h);
+    }
+    resp
+}
+
+/// serve answers http requests on addr until the process is stopped.  A watcher reloads the module
+/// between requests when the wasm file changes.
+pub fn serve(
+    host: &WapcHost,
+    addr: &str,
+    method: Option<&str>,
+    mut watcher: Option<&mut ModuleWatcher>,
+) -> BoxResult<()> {
+    let server = Server::http(addr)?;
+    println!("serving bitcode on http://{addr}");
+    for n in 0.. {
+        if let Some(w) = watcher.as_mut() {
+            w.reload_if_changed(host)?;
+        }
+        let mut req = match server.recv_timeout(WATCH_INTERVAL)? {
+            Some(r) => r,
+            None => continue,
+        };
+        let reply = (|| -> BoxResult<Vec<u8>> {
+            let http = to_http_params(&mut req)?;
+            let m = method
+                .map(String::from)
+                .unwrap_or_else(|| method_for(&http.path));
+            let mut fab = QFAB.lock().map_err(|e| e.to_string())?;
+            fab.reset_call();
+            let request = request_for(http, &format!("serve{n}"), &m, &fab)?;
+            drop(fab);
+            Ok(host.call("_JPC", &serde_json::to_vec(&request)?)?)
+        })();
+        let resp = respond(reply);
+        println!("{} {} -> {}", req.method(), req.url(), resp.status_code().0);
+        if let Err(e) = req.respond(resp) {
+            println!("failed to respond: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_parts() {
+        let q = parse_query("height=200&tag=a+b&tag=c%2Fd&flag&bad=%zz");
+        assert_eq!(q["height"], vec!["200"]);
+        assert_eq!(q["tag"], vec!["a b", "c/d"]);
+        assert_eq!(q["flag"], vec![""]);
+        assert_eq!(q["bad"], vec!["%zz"]);
+        assert_eq!(method_for("/image/x/y.jpg"), "image");
+        assert_eq!(method_for("/"), "");
+    }
+}
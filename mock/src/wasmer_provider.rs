@@ -0,0 +1,236 @@
+//! WasmerEngineProvider runs a waPC guest under wasmer, the counterpart of the wasmtime provider, so the
+//! behaviour of a bitcode module may be compared between the two engines.
+//!
+//! The guest is instantiated in [wapc::WebAssemblyEngineProvider::init] once the host state is known.  The
+//! `wapc` imports move the guest request, the guest response and error and the host call and its response
+//! and error between the guest's linear memory and the [wapc::ModuleState].
+
+use std::error::Error;
+use std::ops::Range;
+use std::sync::Arc;
+
+use wapc::ModuleState;
+use wasmer::{
+    imports, Function, Instance, LazyInit, Memory, Module, NativeFunc, RuntimeError, Store,
+    Universal, WasmerEnv,
+};
+use wasmer_compiler_cranelift::Cranelift;
+
+const HOST_NAMESPACE: &str = "wapc";
+const GUEST_CALL: &str = "__guest_call";
+/// exports run in order once the guest is instantiated, as by the wasmtime provider
+const INIT_FUNCTIONS: &[&str] = &["_start", "wapc_init"];
+
+#[derive(WasmerEnv, Clone)]
+struct WapcEnv {
+    host: Arc<ModuleState>,
+    #[wasmer(export)]
+    memory: LazyInit<Memory>,
+}
+
+/// guest_range checks that the len bytes at ptr lie within a guest memory of size bytes.  Pointers and
+/// lengths come from the guest, an out of bounds access traps the guest rather than the host.
+fn guest_range(ptr: i32, len: usize, size: usize) -> Result<Range<usize>, RuntimeError> {
+    let start = usize::try_from(ptr)
+        .map_err(|_| RuntimeError::new(format!("negative guest pointer {ptr}")))?;
+    match start.checked_add(len) {
+        Some(end) if end <= size => Ok(start..end),
+        _ => Err(RuntimeError::new(format!(
+            "guest access of {len} bytes at {ptr} is beyond its memory of {size} bytes"
+        ))),
+    }
+}
+
+impl WapcEnv {
+    fn memory(&self) -> Result<&Memory, RuntimeError> {
+        self.memory_ref()
+            .ok_or_else(|| RuntimeError::new("the guest does not export its memory"))
+    }
+
+    fn read(&self, ptr: i32, len: i32) -> Result<Vec<u8>, RuntimeError> {
+        let len = usize::try_from(len)
+            .map_err(|_| RuntimeError::new(format!("negative guest length {len}")))?;
+        let view = self.memory()?.view::<u8>();
+        let range = guest_range(ptr, len, view.len())?;
+        Ok(view[range].iter().map(|c| c.get()).collect())
+    }
+
+    fn read_string(&self, ptr: i32, len: i32) -> Result<String, RuntimeError> {
+        Ok(String::from_utf8_lossy(&self.read(ptr, len)?).to_string())
+    }
+
+    fn write(&self, ptr: i32, data: &[u8]) -> Result<(), RuntimeError> {
+        let view = self.memory()?.view::<u8>();
+        let range = guest_range(ptr, data.len(), view.len())?;
+        for (cell, b) in view[range].iter().zip(data) {
+            cell.set(*b);
+        }
+        Ok(())
+    }
+}
+
+fn console_log(env: &WapcEnv, ptr: i32, len: i32) -> Result<(), RuntimeError> {
+    env.host.do_console_log(&env.read_string(ptr, len)?);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn host_call(
+    env: &WapcEnv,
+    bd_ptr: i32,
+    bd_len: i32,
+    ns_ptr: i32,
+    ns_len: i32,
+    op_ptr: i32,
+    op_len: i32,
+    ptr: i32,
+    len: i32,
+) -> Result<i32, RuntimeError> {
+    let binding = env.read_string(bd_ptr, bd_len)?;
+    let ns = env.read_string(ns_ptr, ns_len)?;
+    let op = env.read_string(op_ptr, op_len)?;
+    let payload = env.read(ptr, len)?;
+    Ok(env
+        .host
+        .do_host_call(&binding, &ns, &op, &payload)
+        .unwrap_or(0))
+}
+
+fn host_response(env: &WapcEnv, ptr: i32) -> Result<(), RuntimeError> {
+    match env.host.get_host_response() {
+        Some(r) => env.write(ptr, &r),
+        None => Ok(()),
+    }
+}
+
+fn host_response_len(env: &WapcEnv) -> i32 {
+    env.host
+        .get_host_response()
+        .map(|r| r.len() as i32)
+        .unwrap_or_default()
+}
+
+fn host_error(env: &WapcEnv, ptr: i32) -> Result<(), RuntimeError> {
+    match env.host.get_host_error() {
+        Some(e) => env.write(ptr, e.as_bytes()),
+        None => Ok(()),
+    }
+}
+
+fn host_error_len(env: &WapcEnv) -> i32 {
+    env.host
+        .get_host_error()
+        .map(|e| e.len() as i32)
+        .unwrap_or_default()
+}
+
+fn guest_request(env: &WapcEnv, op_ptr: i32, ptr: i32) -> Result<(), RuntimeError> {
+    if let Some(inv) = env.host.get_guest_request() {
+        env.write(op_ptr, inv.operation.as_bytes())?;
+        env.write(ptr, &inv.msg)?;
+    }
+    Ok(())
+}
+
+fn guest_response(env: &WapcEnv, ptr: i32, len: i32) -> Result<(), RuntimeError> {
+    env.host.set_guest_response(env.read(ptr, len)?);
+    Ok(())
+}
+
+fn guest_error(env: &WapcEnv, ptr: i32, len: i32) -> Result<(), RuntimeError> {
+    env.host.set_guest_error(env.read_string(ptr, len)?);
+    Ok(())
+}
+
+pub struct WasmerEngineProvider {
+    store: Store,
+    module: Module,
+    host: Option<Arc<ModuleState>>,
+    guest_call: Option<NativeFunc<(i32, i32), i32>>,
+}
+
+impl WasmerEngineProvider {
+    /// new compiles the guest with cranelift, instantiation waits for the host state passed to init
+    pub fn new(buf: &[u8]) -> Result<WasmerEngineProvider, Box<dyn Error + Send + Sync>> {
+        let store = Store::new(&Universal::new(Cranelift::new()).engine());
+        let module = Module::new(&store, buf)?;
+        Ok(WasmerEngineProvider {
+            store,
+            module,
+            host: None,
+            guest_call: None,
+        })
+    }
+
+    fn instantiate(&mut self) -> Result<(), Box<dyn Error>> {
+        let host = self
+            .host
+            .clone()
+            .ok_or("the wasmer provider has not been initialized")?;
+        let env = WapcEnv {
+            host,
+            memory: LazyInit::new(),
+        };
+        let s = &self.store;
+        let import_object = imports! {
+            HOST_NAMESPACE => {
+                "__console_log" => Function::new_native_with_env(s, env.clone(), console_log),
+                "__host_call" => Function::new_native_with_env(s, env.clone(), host_call),
+                "__host_response" => Function::new_native_with_env(s, env.clone(), host_response),
+                "__host_response_len" => Function::new_native_with_env(s, env.clone(), host_response_len),
+                "__host_error" => Function::new_native_with_env(s, env.clone(), host_error),
+                "__host_error_len" => Function::new_native_with_env(s, env.clone(), host_error_len),
+                "__guest_request" => Function::new_native_with_env(s, env.clone(), guest_request),
+                "__guest_response" => Function::new_native_with_env(s, env.clone(), guest_response),
+                "__guest_error" => Function::new_native_with_env(s, env, guest_error),
+            }
+        };
+        let instance = Instance::new(&self.module, &import_object)?;
+        for name in INIT_FUNCTIONS {
+            if let Ok(f) = instance.exports.get_native_function::<(), ()>(name) {
+                f.call()?;
+            }
+        }
+        self.guest_call = Some(
+            instance
+                .exports
+                .get_native_function::<(i32, i32), i32>(GUEST_CALL)?,
+        );
+        Ok(())
+    }
+}
+
+impl wapc::WebAssemblyEngineProvider for WasmerEngineProvider {
+    fn init(&mut self, host: Arc<ModuleState>) -> Result<(), Box<dyn Error>> {
+        self.host = Some(host);
+        self.instantiate()
+    }
+
+    fn call(&mut self, op_length: i32, msg_length: i32) -> Result<i32, Box<dyn Error>> {
+        let guest_call = self
+            .guest_call
+            .as_ref()
+            .ok_or("the wasmer provider has not been initialized")?;
+        Ok(guest_call.call(op_length, msg_length)?)
+    }
+
+    fn replace(&mut self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.module = Module::new(&self.store, bytes)?;
+        self.instantiate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::guest_range;
+
+    #[test]
+    fn test_guest_range() {
+        assert_eq!(guest_range(8, 4, 16).unwrap(), 8..12);
+        assert_eq!(guest_range(12, 4, 16).unwrap(), 12..16);
+        assert!(guest_range(13, 4, 16).is_err());
+        assert!(guest_range(32, 0, 16).is_err());
+        assert!(guest_range(-1, 1, 16).is_err());
+        assert!(guest_range(i32::MAX, usize::MAX, 16).is_err());
+    }
+}
@@ -19,15 +19,12 @@ use std::path::Path;
 
 use elvwasm::ErrorKinds;
 
-implement_bitcode_module!(
-    "download",
-    do_download,
-    "bulk_download",
-    do_bulk_download,
-    "preview",
-    do_preview,
-    "thumbnail",
-    do_preview
+implement_bitcode_module!("bulk_download", do_bulk_download;
+    routes {
+        "*" "/download/*asset" => do_download,
+        "*" "/preview/*asset" => do_preview,
+        "*" "/thumbnail/*asset" => do_thumbnail,
+    }
 );
 
 const VERSION: &str = "1.1.3";
@@ -184,10 +181,7 @@ fn process_multi_entry(bcc: &BitcodeContext, link: &str) -> CallResult {
 fn do_bulk_download(bcc: &mut BitcodeContext) -> CallResult {
     let http_p = &bcc.request.params.http;
     let qp = &http_p.query;
-    let path_vec: Vec<&str> = bcc.request.params.http.path.split('/').collect();
-    bcc.log_debug(&format!(
-        "In Assets path_vec = {path_vec:?} http params = {http_p:?}"
-    ))?;
+    bcc.log_debug(&format!("In Assets http params = {http_p:?}"))?;
 
     bcc.log_debug("do_bulk_download")?;
 
@@ -326,7 +320,7 @@ fn do_single_asset(
 ) -> CallResult {
     bcc.log_debug("do_single_asset")?;
     let asset_path = Path::new("/assets")
-        .join(Path::new(asset).strip_prefix("/").unwrap())
+        .join(asset)
         .to_string_lossy()
         .into_owned();
     let meta: serde_json::Value = serde_json::from_slice(&bcc.sqmd_get_json(&asset_path)?)?;
@@ -386,28 +380,34 @@ fn get_single_offering_image(bcc: &BitcodeContext, url: &str, is_video: bool) ->
     bcc.fetch_link_reader(json!(format!("./rep{url}")))
 }
 
+// The asset is the remainder of the path following the operation, e.g. /download/11e1e-45d4a.jpg
+fn do_routed_asset(bcc: &BitcodeContext, operation: &str, is_download: bool) -> CallResult {
+    let http_p = &bcc.request.params.http;
+    let asset = match bcc.path_param("asset") {
+        Some(a) if !a.is_empty() => a,
+        _ => {
+            return Err(Box::new(ErrorKinds::BadHttpParams(format!(
+                "no asset in path {}",
+                http_p.path
+            ))))
+        }
+    };
+    do_single_asset(bcc, &http_p.query, operation, asset, is_download)
+}
+
 #[no_mangle]
 fn do_download(bcc: &mut BitcodeContext) -> CallResult {
-    let req = &bcc.request;
-    do_single_asset(
-        bcc,
-        &req.params.http.query,
-        &req.method,
-        &req.params.http.path,
-        true,
-    )
+    do_routed_asset(bcc, "download", true)
 }
 
 #[no_mangle]
 fn do_preview(bcc: &mut BitcodeContext) -> CallResult {
-    let req = &bcc.request;
-    do_single_asset(
-        bcc,
-        &req.params.http.query,
-        &req.method,
-        &req.params.http.path,
-        false,
-    )
+    do_routed_asset(bcc, "preview", false)
+}
+
+#[no_mangle]
+fn do_thumbnail(bcc: &mut BitcodeContext) -> CallResult {
+    do_routed_asset(bcc, "thumbnail", false)
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -2,15 +2,13 @@ extern crate base64;
 extern crate elvwasm;
 extern crate serde;
 extern crate serde_json;
-#[macro_use(defer)]
-extern crate scopeguard;
 
 use base64::{engine::general_purpose, Engine as _};
 use elvwasm::bccontext_fabric_io::FabricStreamWriter;
 use elvwasm::BitcodeContext;
 use elvwasm::{
     implement_bitcode_module, jpc, register_handler, CreatePartResult, ErrorKinds,
-    ExternalCallResult, FinalizeCallResult, NewStreamResult,
+    ExternalCallResult, FinalizeCallResult,
 };
 use serde_json::json;
 use std::convert::TryInto;
@@ -56,22 +54,18 @@ fn do_external_fail(bcc: &mut BitcodeContext) -> CallResult {
         exr.fout.len()
     ))?;
     bcc.log_debug(&format!("fout {}", &exr.fout))?;
-    let stream_img: NewStreamResult = bcc.new_stream().try_into()?;
-    defer! {
-        bcc.log_debug(&format!("Closing part stream {}", &stream_img.stream_id)).unwrap_or_default();
-        let _ = bcc.close_stream(stream_img.stream_id.clone());
-    }
-    let mut fsw = FabricStreamWriter::new(bcc, stream_img.stream_id.clone(), imgbits.len());
+    let stream_img = bcc.new_stream()?;
+    let mut fsw = FabricStreamWriter::new(bcc, stream_img.stream_id().to_string(), imgbits.len());
     fsw.write_all(imgbits)?;
     let imgpart: CreatePartResult = bcc
-        .q_create_part_from_stream(&bcc.request.q_info.write_token, &stream_img.stream_id)
+        .q_create_part_from_stream(&bcc.request.q_info.qwtoken()?, stream_img.stream_id())
         .try_into()?;
     bcc.log_debug(&format!(
         "imgpart hash {} size = {}",
         &imgpart.qphash, imgpart.size
     ))?;
     let fc: FinalizeCallResult = bcc
-        .q_finalize_content(&bcc.request.q_info.write_token)
+        .q_finalize_content(&bcc.request.q_info.qwtoken()?)
         .try_into()?;
     let tar_params = json!({
         "http" : {
@@ -139,21 +133,17 @@ fn do_external(bcc: &mut BitcodeContext) -> CallResult {
         exr.fout.len()
     ));
     bcc.log_debug(&format!("fout {}", &exr.fout))?;
-    let stream_img: NewStreamResult = bcc.new_stream().try_into()?;
-    defer! {
-        bcc.log_debug(&format!("Closing part stream {}", &stream_img.stream_id)).unwrap_or_default();
-        let _ = bcc.close_stream(stream_img.stream_id.clone());
-    }
-    bcc.write_stream(&stream_img.stream_id, imgbits)?;
+    let stream_img = bcc.new_stream()?;
+    bcc.write_stream(stream_img.stream_id(), imgbits)?;
     let imgpart: CreatePartResult = bcc
-        .q_create_part_from_stream(&bcc.request.q_info.write_token, &stream_img.stream_id)
+        .q_create_part_from_stream(&bcc.request.q_info.qwtoken()?, stream_img.stream_id())
         .try_into()?;
     bcc.log_debug(&format!(
         "imgpart hash {} size = {}",
         &imgpart.qphash, imgpart.size
     ))?;
     let fc: FinalizeCallResult = bcc
-        .q_finalize_content(&bcc.request.q_info.write_token)
+        .q_finalize_content(&bcc.request.q_info.qwtoken()?)
         .try_into()?;
     let tar_params = json!({
         "http" : {
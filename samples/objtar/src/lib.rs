@@ -4,13 +4,10 @@ extern crate base64;
 extern crate elvwasm;
 extern crate serde;
 extern crate serde_json;
-#[macro_use(defer)]
-extern crate scopeguard;
 
 use elvwasm::{
     bccontext_fabric_io::{FabricStreamReader, FabricStreamWriter},
-    implement_bitcode_module, jpc, register_handler, FileStream, NewStreamResult, QPartList,
-    SystemTimeResult,
+    implement_bitcode_module, jpc, register_handler, IdHashOrToken, QPartList, SystemTimeResult,
 };
 use flate2::write::GzEncoder;
 use serde_json::json;
@@ -29,10 +26,9 @@ implement_bitcode_module!(
 fn do_tar_from_obj(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     let http_p = &bcc.request.params.http;
     let qp = &http_p.query;
-    let vqhot = &vec![bcc.request.q_info.qhot()];
-    let obj_id = match qp.get("object_id_or_hash") {
-        Some(x) => x,
-        None => vqhot,
+    let obj_id: IdHashOrToken = match qp.get("object_id_or_hash") {
+        Some(x) => x[0].parse()?,
+        None => bcc.request.q_info.qhot()?.into(),
     };
     const DEF_CAP: usize = 50000000;
     let buf_cap = match qp.get("buffer_capacity") {
@@ -47,32 +43,29 @@ fn do_tar_from_obj(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     {
         let bw = BufWriter::with_capacity(buf_cap, &mut fw);
 
-        let pl: QPartList = bcc.q_part_list(obj_id[0].to_string()).try_into()?;
+        let pl: QPartList = bcc.q_part_list(&obj_id).try_into()?;
+        let qihot = bcc.request.q_info.qhot()?;
 
         let zip = GzEncoder::new(bw, flate2::Compression::default());
         let mut a = tar::Builder::new(zip);
         let time_cur: SystemTimeResult = bcc.q_system_time().try_into()?;
         for part in pl.part_list.parts {
-            let stream_wm: NewStreamResult = bcc.new_stream().try_into()?;
-            defer! {
-                bcc.log_debug(&format!("Closing part stream {}", &stream_wm.stream_id)).unwrap_or_default();
-                let _ = bcc.close_stream(stream_wm.stream_id.clone());
-            }
+            let stream_wm = bcc.new_stream()?;
             let _wprb = bcc.write_part_to_stream(
-                stream_wm.stream_id.clone(),
-                part.hash.clone(),
-                bcc.request.q_info.hash.clone(),
+                stream_wm.stream_id().to_string(),
+                &part.hash,
+                &qihot,
                 0,
                 -1,
                 false,
             )?;
             let usz = part.size as u64;
-            let fsr = FabricStreamReader::new(stream_wm.stream_id.clone(), bcc);
+            let fsr = stream_wm.reader();
             let mut header = tar::Header::new_gnu();
             header.set_size(usz);
             header.set_cksum();
             header.set_mtime(time_cur.time);
-            a.append_data(&mut header, part.hash.clone(), fsr)?;
+            a.append_data(&mut header, part.hash.as_str(), fsr)?;
         }
         a.finish()?;
         let mut finished_writer = a.into_inner()?;
@@ -86,12 +79,8 @@ fn do_tar_from_obj(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
 
 #[no_mangle]
 pub fn do_seeker(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
-    let fstream: FileStream = bcc.new_file_stream().try_into()?;
-    defer! {
-        bcc.log_debug(&format!("Closing part stream {}", &fstream.stream_id)).unwrap_or_default();
-        let _ = bcc.close_stream(fstream.stream_id.clone());
-    }
-    let mut fsw = FabricStreamWriter::new(bcc, fstream.stream_id.clone(), 0);
+    let fstream = bcc.new_file_stream()?;
+    let mut fsw = FabricStreamWriter::new(bcc, fstream.stream_id().to_string(), 0);
 
     // Write a known string into the fabric file
     let known_string = "Hello, world! This is a test string for seeking.";
@@ -100,7 +89,7 @@ pub fn do_seeker(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     let expected = ["Hello, wor", "world! Thi", " This is a", "est string"];
 
     // Use a reader to seek to different positions and read the data
-    let mut fsr = FabricStreamReader::new(fstream.stream_id.clone(), bcc);
+    let mut fsr = FabricStreamReader::new(fstream.stream_id().to_string(), bcc);
     let positions = [0, 7, 13, 25];
     for (iter, &pos) in positions.iter().enumerate() {
         let seek_return = fsr.seek(SeekFrom::Start(pos))?;
@@ -123,6 +112,6 @@ pub fn do_seeker(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     }
 
     // Close the stream
-    bcc.close_stream(fstream.stream_id.clone())?;
+    fstream.close()?;
     bcc.make_success_json(&json!({}))
 }
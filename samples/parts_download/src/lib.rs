@@ -4,15 +4,13 @@ extern crate base64;
 extern crate elvwasm;
 extern crate serde;
 extern crate serde_json;
-#[macro_use(defer)]
-extern crate scopeguard;
 const VERSION: &str = "1.1.3.1";
 
 use std::collections::HashMap;
 
 use elvwasm::{
-    bccontext_fabric_io::{FabricStreamReader, FabricStreamWriter},
-    implement_bitcode_module, jpc, register_handler, NewStreamResult, QPartList, SystemTimeResult,
+    bccontext_fabric_io::FabricStreamWriter, implement_bitcode_module, jpc, register_handler,
+    HashOrToken, QPartHash, QPartList, SystemTimeResult,
 };
 use serde_json::json;
 use std::io::{BufWriter, Write};
@@ -95,30 +93,25 @@ fn do_parts_download(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     };
     let mut total_size = 0;
     if !part_hash.is_empty() {
-        let part = part_hash[0].clone();
-        let stream_wm: NewStreamResult = bcc.new_stream().try_into()?;
-        defer! {
-            bcc.log_debug(&format!("Closing part stream {}", &stream_wm.stream_id)).unwrap_or_default();
-            let _ = bcc.close_stream(stream_wm.stream_id.clone());
-        }
+        let part: QPartHash = part_hash[0].parse()?;
+        let qihot = HashOrToken::Hash(bcc.request.q_info.qhash()?);
+        let stream_wm = bcc.new_stream()?;
         let _wprb = bcc.write_part_to_stream(
-            stream_wm.stream_id.clone(),
-            part.clone(),
-            bcc.request.q_info.hash.clone(),
+            stream_wm.stream_id().to_string(),
+            &part,
+            &qihot,
             0,
             -1,
             true,
         )?;
-        let pl: QPartList = bcc
-            .q_part_list(bcc.request.q_info.hash.to_string())
-            .try_into()?;
+        let pl: QPartList = bcc.q_part_list(&qihot.clone().into()).try_into()?;
         pl.part_list.parts.iter().for_each(|x| {
             if x.hash == part {
                 total_size = x.size;
             }
         });
         let usz = total_size.try_into()?;
-        let mut fsr = FabricStreamReader::new(stream_wm.stream_id.clone(), bcc);
+        let mut fsr = stream_wm.reader();
         let mut fsw = FabricStreamWriter::new(bcc, "fos".to_string(), usz);
         std::io::copy(&mut fsr, &mut fsw)?;
         bcc.callback_disposition(200, "application/octet-stream", usz, &content_disp, VERSION)?;
@@ -128,33 +121,28 @@ fn do_parts_download(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     {
         let bw = BufWriter::with_capacity(buf_cap, &mut fw);
 
-        let pl: QPartList = bcc
-            .q_part_list(bcc.request.q_info.hash.clone())
-            .try_into()?;
+        let qihot = HashOrToken::Hash(bcc.request.q_info.qhash()?);
+        let pl: QPartList = bcc.q_part_list(&qihot.clone().into()).try_into()?;
 
         let mut a = tar::Builder::new(bw);
         let time_cur: SystemTimeResult = bcc.q_system_time().try_into()?;
         for part in pl.part_list.parts {
-            let stream_wm: NewStreamResult = bcc.new_stream().try_into()?;
-            defer! {
-                bcc.log_debug(&format!("Closing part stream {}", &stream_wm.stream_id)).unwrap_or_default();
-                let _ = bcc.close_stream(stream_wm.stream_id.clone());
-            }
+            let stream_wm = bcc.new_stream()?;
             let _wprb = bcc.write_part_to_stream(
-                stream_wm.stream_id.clone(),
-                part.hash.clone(),
-                bcc.request.q_info.hash.clone(),
+                stream_wm.stream_id().to_string(),
+                &part.hash,
+                &qihot,
                 0,
                 -1,
                 true,
             )?;
             let usz = part.size.try_into()?;
-            let fsr = FabricStreamReader::new(stream_wm.stream_id.clone(), bcc);
+            let fsr = stream_wm.reader();
             let mut header = tar::Header::new_gnu();
             header.set_size(usz);
             header.set_mode(0o644);
             header.set_mtime(time_cur.time);
-            header.set_path(&part.hash)?;
+            header.set_path(part.hash.as_str())?;
             header.set_cksum();
 
             a.append(&header, fsr)?;
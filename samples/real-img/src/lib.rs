@@ -1,10 +1,8 @@
 extern crate elvwasm;
 extern crate serde_json;
-#[macro_use(defer)]
-extern crate scopeguard;
 use std::collections::HashMap;
 
-use elvwasm::{bccontext_fabric_io::FabricStreamReader, ErrorKinds};
+use elvwasm::{bccontext_fabric_io::FabricStreamReader, ErrorKinds, HashOrToken};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -12,11 +10,14 @@ extern crate image;
 use image::jpeg::JpegEncoder;
 use image::GenericImageView;
 
-use elvwasm::{
-    implement_bitcode_module, jpc, register_handler, BitcodeContext, NewStreamResult, WriteResult,
-};
+use elvwasm::{implement_bitcode_module, jpc, register_handler, BitcodeContext, WriteResult};
 
-implement_bitcode_module!("image", do_img, "content", do_img);
+implement_bitcode_module!("image", do_img, "content", do_img;
+    routes {
+        "*" "/image/{offering}/*asset" => do_img,
+        "*" "/content/{offering}/*asset" => do_img,
+    }
+);
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct WatermarkJson {
@@ -38,23 +39,8 @@ pub struct ImageWatermark {
     pub image_watermark: WatermarkJson,
 }
 
-fn parse_asset(path: &str) -> String {
-    let pos: Vec<&str> = path.split('/').collect();
-    if pos.len() > 2 {
-        let new_pos: Vec<&str> = pos[3..].to_vec();
-        return "/".to_string() + &new_pos.join("/");
-    }
-    "".to_owned()
-}
-
-fn get_offering(bcc: &BitcodeContext, input_path: &str) -> CallResult {
-    let v: Vec<&str> = input_path.split('/').collect();
-    let mut s = "";
-    if v.len() > 1 {
-        s = v[2];
-    }
-    let json_path = format!("/image/offerings/{s}");
-    // input_path should just be offering
+fn get_offering(bcc: &BitcodeContext, offering: &str) -> CallResult {
+    let json_path = format!("/image/offerings/{offering}");
     bcc.sqmd_get_json(&json_path)
 }
 
@@ -63,8 +49,11 @@ fn fab_file_to_image(
     stream_id: &str,
     asset_path: &str,
 ) -> image::ImageResult<image::DynamicImage> {
+    let qhash = bcc.request.q_info.qhash().map_err(|e| {
+        image::ImageError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    })?;
     let _written: WriteResult = match bcc
-        .q_file_to_stream(stream_id, asset_path, &bcc.request.q_info.hash)
+        .q_file_to_stream(stream_id, asset_path, &HashOrToken::Hash(qhash))
         .try_into()
     {
         Ok(v) => v,
@@ -83,16 +72,25 @@ fn fab_file_to_image(
 
 fn do_img(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     let http_p = &bcc.request.params.http;
-    let offering_json: ImageWatermark = elvwasm::convert(&get_offering(bcc, &http_p.path))?;
-    let asset_path = parse_asset(&http_p.path);
+    let offering = bcc.path_param("offering").ok_or(ErrorKinds::BadHttpParams(format!(
+        "no offering in path {}",
+        http_p.path
+    )))?;
+    let offering_json: ImageWatermark = elvwasm::convert(&get_offering(bcc, offering))?;
+    let asset_path = match bcc.path_param("asset") {
+        Some(a) if !a.is_empty() => format!("/{a}"),
+        _ => {
+            return Err(Box::new(ErrorKinds::BadHttpParams(format!(
+                "no asset in path {}",
+                http_p.path
+            ))))
+        }
+    };
     bcc.log_debug(&format!(
         "offering = {:?} asset_path = {} http_path= {}",
         &offering_json, &asset_path, &http_p.path
     ))?;
-    let stream_main: NewStreamResult = bcc.new_stream().try_into()?;
-    defer! {
-      let _ = bcc.close_stream(stream_main.stream_id.clone());
-    }
+    let stream_main = bcc.new_stream()?;
     let qp = &http_p.query;
     let v_none = vec!["".to_string()];
 
@@ -100,7 +98,7 @@ fn do_img(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
         .get("header-x_set_content_disposition")
         .unwrap_or(&v_none);
 
-    let img = &mut fab_file_to_image(&bcc, &stream_main.stream_id, &asset_path)?;
+    let img = &mut fab_file_to_image(&bcc, stream_main.stream_id(), &asset_path)?;
     let (w, h) = img.dimensions();
     let v = &vec![h.to_string()];
     let height_str = &http_p.query.get("height").unwrap_or(v);
@@ -117,10 +115,7 @@ fn do_img(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
         image::imageops::FilterType::Lanczos3,
     );
     if !offering_json.image_watermark.image.is_empty() {
-        let stream_wm: NewStreamResult = bcc.new_stream().try_into()?;
-        defer! {
-          let _ = bcc.close_stream(stream_wm.stream_id.clone());
-        }
+        let stream_wm = bcc.new_stream()?;
         let wm_filename = match offering_json.image_watermark.image.get("/") {
             Some(f) => f
                 .as_str()
@@ -132,7 +127,7 @@ fn do_img(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
             }
         };
         bcc.log_debug(&format!("watermark image {}", &wm_filename[7..]))?;
-        let wm = fab_file_to_image(&bcc, &stream_wm.stream_id, &wm_filename[7..])?;
+        let wm = fab_file_to_image(&bcc, stream_wm.stream_id(), &wm_filename[7..])?;
         let wm_height_scale = offering_json.image_watermark.height;
         let opacity = offering_json.image_watermark.opacity;
         let mut wm_thumb = image::imageops::thumbnail(
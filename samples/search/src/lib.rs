@@ -18,7 +18,7 @@ use elvwasm::ErrorKinds;
 use indexer::Indexer;
 use snailquote::unescape;
 
-use elvwasm::{implement_bitcode_module, jpc, register_handler};
+use elvwasm::{implement_bitcode_module, jpc, register_handler, QHash, QPartHash};
 
 implement_bitcode_module!(
     "crawl",
@@ -229,10 +229,10 @@ fn do_search(bcc: &mut elvwasm::BitcodeContext) -> CallResult {
     }
     bcc.log_debug("do search")?;
 
-    let part_hash = &qp["part-hash"][0];
-    let content_hash = &qp["content-hash"][0];
+    let part_hash: QPartHash = qp["part-hash"][0].parse()?;
+    let content_hash: QHash = qp["content-hash"][0].parse()?;
 
-    bcc.restore_index_from_part(content_hash, part_hash)?;
+    bcc.restore_index_from_part(&content_hash, &part_hash)?;
     let ft_json: serde_json::Value = serde_json::from_slice(&bcc.builder_add_text_field(Some(
         json!({ "field_name": "title", "type": 2_u8, "stored": true}),
     ))?)?;
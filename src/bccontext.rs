@@ -4,9 +4,13 @@ extern crate serde_json;
 extern crate thiserror;
 extern crate wapc_guest as guest;
 
-use crate::{get_cargo_version, get_git_version, make_json_error, ErrorKinds};
-use crate::{FileStream, NewStreamResult, Request, Response};
+use crate::bccontext_typed_api::decode_result;
+use crate::{make_json_error, ErrorKinds, FabricError};
+use crate::{
+    FileStream, HashOrToken, NewStreamResult, QWriteToken, Request, Response, WriteResult,
+};
 
+use serde::de::DeserializeOwned;
 use serde_json::json;
 
 use std::fmt::Debug;
@@ -14,6 +18,8 @@ use std::fmt::Debug;
 use std::collections::HashMap;
 use std::str;
 
+#[cfg(any(test, feature = "testing"))]
+use crate::bccontext_testing::host_call;
 use guest::prelude::*;
 
 pub fn convert<'b, T>(cr: &'b CallResult) -> Result<T, Box<dyn std::error::Error + Sync + Send>>
@@ -32,183 +38,23 @@ where
 #[derive(Debug, Clone, Default)]
 pub struct BitcodeContext {
     pub request: Request,
+    /// named segments captured by the route that dispatched this request (see [crate::register_route])
+    pub path_params: HashMap<String, String>,
 }
 
 impl<'a> BitcodeContext {
     pub fn new(request: Request) -> BitcodeContext {
-        BitcodeContext { request }
-    }
-
-    pub fn log_info(&'a self, s: &str) -> CallResult {
-        self.call_function("Log", json!({"level" : "INFO", "msg" : s}), "ctx")
-    }
-
-    pub fn log_debug(&'a self, s: &str) -> CallResult {
-        self.call_function("Log", json!({"level" : "DEBUG", "msg" : s}), "ctx")
-    }
-
-    pub fn log_warn(&'a self, s: &str) -> CallResult {
-        self.call_function("Log", json!({"level" : "WARN", "msg" : s}), "ctx")
-    }
-
-    pub fn log_error(&'a self, s: &str) -> CallResult {
-        self.call_function("Log", json!({"level" : "ERROR", "msg" : s}), "ctx")
-    }
-
-    /// write_stream writes a u8 slice of specified length to a fabric stream
-    /// # Arguments
-    /// * `id`-    a unique identifier (can use BitcodeContext's request id)
-    /// * `stream`-  the fabric stream to write to [BitcodeContext::new_stream]
-    /// * `src`-  a u8 slice to write
-    /// * `len` -  length of the slice to write
-    /// # Returns
-    /// utf8 bytes stream containing json
-    /// { "written" : bytes }
-    ///
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/019b88ac27635d5022c2211751f6af5957df2463/samples/external/src/lib.rs#L111)
-    ///
-    pub fn write_stream(&'a self, stream: &str, src: &'a [u8]) -> CallResult {
-        host_call(&self.request.id, stream, "Write", src)
-    }
-
-    /// seek_stream seeks within a fabric stream
-    /// # Arguments
-    /// * `id`-    a unique identifier (can use BitcodeContext's request id)
-    /// * `stream`-  the fabric stream to write to [BitcodeContext::new_stream]
-    /// * `offset`-  offset to seek to
-    /// * `whence` - 0, 1, 2 for start, current, end
-    /// # Returns
-    /// utf8 bytes stream containing json
-    /// { "offset" : offset }
-    ///
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/019b88ac27635d5022c2211751f6af5957df2463/samples/external/src/lib.rs#L111)
-    ///
-    pub fn seek_stream(&'a self, stream: &str, offset: i64, whence: i8) -> CallResult {
-        host_call(
-            &self.request.id,
-            stream,
-            "Seek",
-            serde_json::to_string(&json!({ "offset": offset, "whence": whence }))?.as_bytes(),
-        )
-    }
-
-    /// read_stream reads usize bytes from a fabric stream returning a slice of [u8]
-    /// # Arguments
-    /// * `stream_to_read`-  the fabric stream to read from
-    // / * `sz`-  usize size of bytes (0 size will read the entire stream)
-    /// # Returns
-    ///   byte slice of the read stream
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/019b88ac27635d5022c2211751f6af5957df2463/samples/objtar/src/lib.rs#L112)
-    ///
-    pub fn read_stream(&'a self, stream_to_read: String, sz: usize) -> CallResult {
-        self.log_debug(&format!("imput len = {}", sz))?;
-        // Read was previously being called with a json object containing the length
-        // resulting in a full read of the part and itsd subsequent return as a base64 encoded string
-        // The new convention is to call Reader which will return a byte slice or error
-        host_call(
-            self.request.id.as_str(),
-            stream_to_read.as_str(),
-            "Reader",
-            serde_json::to_string(&json!({ "len": sz }))?.as_bytes(),
-        )
-    }
-
-    /// read_stream_inline reads usize bytes from a fabric stream returning a slice of [u8]
-    /// # Arguments
-    /// * `stream_to_read`-  the fabric stream to read from
-    /// * `sz`-  usize size of bytes
-    /// # Returns
-    /// utf8 bytes stream containing json
-    /// {
-    ///   "return" : { "read" : byte-count-read },
-    ///   "result" : "base64 encoded string"
-    ///  }
-    ///  this api is provided for backward compatibility with the previous read_stream api
-    /// and should be avoided in new code
-    pub fn read_stream_inline(&'a self, stream_to_read: String, sz: usize) -> CallResult {
-        self.log_debug(&format!("imput len = {}", sz))?;
-        host_call(
-            self.request.id.as_str(),
-            stream_to_read.as_str(),
-            "Read",
-            serde_json::to_string(&json!({ "len": sz }))?.as_bytes(),
-        )
-    }
-
-    /// callback issues a Callback on the fabric setting up an expectation that the output stream
-    /// contains a specified sized buffer
-    /// # Arguments
-    /// * `status`-    the http status of the call
-    /// * `content-type`-     output buffer contents
-    /// * `size`-  size of the output contents
-    /// # Returns
-    /// the checksum as hex-encoded string
-    ///
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/019b88ac27635d5022c2211751f6af5957df2463/samples/external/src/lib.rs#L133)
-    ///
-    pub fn callback(&'a self, status: usize, content_type: &str, size: usize) -> CallResult {
-        let v = json!(
-          {"http" : {
-            "status": status,
-            "headers": {
-              "Content-Type": [content_type],
-              "Content-Length": [size.to_string()],
-              "X-Content-Fabric-Bitcode-Version": vec![&get_cargo_version(), &get_git_version()],
-            }
-            }
-          }
-        );
-        let method = "Callback";
-        self.call_function(method, v, "ctx")
+        BitcodeContext {
+            request,
+            path_params: HashMap::new(),
+        }
     }
 
-    /// callback_disposition issues a Callback on the fabric setting up an expectation that the output stream
-    /// contains a specified sized buffer
+    /// path_param returns the named path segment captured by the route for this request
     /// # Arguments
-    /// * `status`-    the http status of the call
-    /// * `content-type`-     output buffer contents
-    /// * `size`-  size of the output contents
-    /// * `disp`-  content disposition
-    /// # Returns
-    /// the checksum as hex-encoded string
-    ///
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/019b88ac27635d5022c2211751f6af5957df2463/samples/external/src/lib.rs#L133)
-    ///
-    pub fn callback_disposition(
-        &'a self,
-        status: usize,
-        content_type: &str,
-        size: usize,
-        disp: &str,
-        version: &str,
-    ) -> CallResult {
-        let mut v = json!(
-          {"http" : {
-            "status": status,
-            "headers": {
-              "Content-Type": vec![content_type],
-              "Content-Length": vec![size.to_string()],
-              "Content-Disposition": vec![disp],
-              "X-Content-Fabric-Bitcode-Version": vec![version, &get_cargo_version(), &get_git_version()],
-            }
-            }
-          }
-        );
-        if size == 0 {
-            v = json!(
-              {"http" : {
-                "status": status,
-                "headers": {
-                  "Content-Type": vec![content_type],
-                  "Content-Disposition": vec![disp],
-                  "X-Content-Fabric-Bitcode-Version": vec![version, &get_cargo_version(), &get_git_version()],
-                }
-                }
-              }
-            );
-        }
-        let method = "Callback";
-        self.call_function(method, v, "ctx")
+    /// * `name` - the name of the segment in the route pattern e.g. `offering` for `/image/{offering}`
+    pub fn path_param(&'a self, name: &str) -> Option<&'a str> {
+        self.path_params.get(name).map(|s| s.as_str())
     }
 
     pub fn make_success(&'a self, msg: &str) -> CallResult {
@@ -229,12 +75,22 @@ impl<'a> BitcodeContext {
         make_json_error(ErrorKinds::Invalid(msg.to_string()), &self.request.id)
     }
 
-    pub fn make_error_with_kind(&'a self, kind: ErrorKinds) -> CallResult {
+    /// make_error_with_kind makes a jpc error response from an [ErrorKinds] or a [FabricError] carrying
+    /// data and a cause chain
+    pub fn make_error_with_kind<E: Into<FabricError>>(&'a self, kind: E) -> CallResult {
         make_json_error(kind, &self.request.id)
     }
 
-    pub fn make_error_with_error<T>(&'a self, kind: ErrorKinds, _err: T) -> CallResult {
-        make_json_error(kind, &self.request.id)
+    /// make_error_with_error makes a jpc error response of the given kind recording err as its cause
+    pub fn make_error_with_error<T: std::fmt::Display>(
+        &'a self,
+        kind: ErrorKinds,
+        err: T,
+    ) -> CallResult {
+        make_json_error(
+            kind.with_cause(ErrorKinds::Other(err.to_string())),
+            &self.request.id,
+        )
     }
 
     pub fn make_success_bytes(&'a self, msg: &[u8], id: &str) -> CallResult {
@@ -247,6 +103,26 @@ impl<'a> BitcodeContext {
     pub fn call(&'a self, ns: &str, op: &str, msg: &[u8]) -> CallResult {
         host_call(self.request.id.as_str(), ns, op, msg)
     }
+
+    /// unwrap_reply extracts the `result` member of a host reply.  A reply carrying an `error` member is
+    /// returned as Err of a [FabricError] whose kind is mapped from the fabric error (see [FabricError::from_fabric])
+    fn unwrap_reply(call_ret_val: Vec<u8>) -> CallResult {
+        let j_res: serde_json::Value = serde_json::from_slice(&call_ret_val)?;
+        if !j_res.is_object() {
+            return Ok(call_ret_val);
+        }
+        match j_res.get("result") {
+            Some(x) => {
+                let r = serde_json::to_vec(&x)?;
+                Ok(r)
+            }
+            None => match j_res.get("error") {
+                Some(x) => Err(Box::new(FabricError::from_fabric(x))),
+                None => Ok(call_ret_val),
+            },
+        }
+    }
+
     /// call_function - enables the calling of fabric api's
     /// # Arguments
     /// * `fn_name` - the fabric api to call e.g. QCreateFileFromStream
@@ -254,12 +130,30 @@ impl<'a> BitcodeContext {
     /// * `module` - one of {"core", "ctx", "ext"} see [fabric API]
     ///
     ///  This is the main workhorse function for the invoking of fabric bitcode APIs
-    ///  wherein all the outer wrapper functions merely call this with the appropriate json parameters
+    ///  wherein all the outer wrapper functions merely call this with the appropriate json parameters.
+    ///  Errors reported by the fabric are returned as Err, see [BitcodeContext::call_function_raw] to
+    ///  receive the reply envelope unchanged.
     pub(crate) fn call_function(
         &'a self,
         fn_name: &str,
         params: serde_json::Value,
         module: &str,
+    ) -> CallResult {
+        Self::unwrap_reply(self.call_function_raw(fn_name, params, module)?)
+    }
+
+    /// call_function_raw - calls a fabric api returning the reply envelope unchanged
+    /// # Arguments
+    /// * `fn_name` - the fabric api to call e.g. QCreateFileFromStream
+    /// * `params` - a json block to pass as parameters to the function being called
+    /// * `module` - one of {"core", "ctx", "ext"} see [fabric API]
+    /// # Returns
+    /// utf8 bytes stream containing json of the form `{"result" : ...}` or `{"error" : ...}`
+    pub fn call_function_raw(
+        &'a self,
+        fn_name: &str,
+        params: serde_json::Value,
+        module: &str,
     ) -> CallResult {
         let response = &Response {
             jpc: "1.0".to_string(),
@@ -270,24 +164,22 @@ impl<'a> BitcodeContext {
         };
         let call_val = serde_json::to_vec(response)?;
 
-        let call_ret_val = host_call(self.request.id.as_str(), module, fn_name, &call_val)?;
-        let j_res: serde_json::Value = serde_json::from_slice(&call_ret_val)?;
-        if !j_res.is_object() {
-            return Ok(call_ret_val);
-        }
-        match j_res.get("result") {
-            Some(x) => {
-                let r = serde_json::to_vec(&x)?;
-                Ok(r)
-            }
-            None => match j_res.get("error") {
-                Some(x) => {
-                    let r = serde_json::to_vec(&x)?;
-                    Ok(r)
-                }
-                None => Ok(call_ret_val),
-            },
-        }
+        host_call(self.request.id.as_str(), module, fn_name, &call_val)
+    }
+
+    /// call_function_typed - calls a fabric api decoding its result into `T`
+    /// # Arguments
+    /// see [BitcodeContext::call_function_raw]
+    /// # Returns
+    /// the decoded result or the [ErrorKinds] reported by the fabric.  A result that cannot be decoded into
+    /// `T` is reported as [ErrorKinds::Invalid]
+    pub fn call_function_typed<T: DeserializeOwned>(
+        &'a self,
+        fn_name: &str,
+        params: serde_json::Value,
+        module: &str,
+    ) -> Result<T, ErrorKinds> {
+        decode_result(fn_name, self.call_function(fn_name, params, module))
     }
 
     /// call_external_bitcode - enables the calling of fabric api's
@@ -341,78 +233,66 @@ impl<'a> BitcodeContext {
         args: &serde_json::Value,
         object_hash: &str,
         code_part_hash: &str,
+    ) -> CallResult {
+        Self::unwrap_reply(self.call_external_bitcode_raw(
+            function,
+            args,
+            object_hash,
+            code_part_hash,
+        )?)
+    }
+
+    /// call_external_bitcode_raw - calls a function on external bitcode returning the reply envelope unchanged
+    /// # Arguments
+    /// see [BitcodeContext::call_external_bitcode]
+    /// # Returns
+    /// utf8 bytes stream containing json of the form `{"result" : ...}` or `{"error" : ...}`
+    pub fn call_external_bitcode_raw(
+        &'a self,
+        function: &str,
+        args: &serde_json::Value,
+        object_hash: &str,
+        code_part_hash: &str,
     ) -> CallResult {
         let params = json!({ "module": "".to_string() ,"function": function,  "params" : args, "object_hash" : object_hash, "code_part_hash" : code_part_hash});
         let call_val = serde_json::to_vec(&params)?;
 
-        let call_ret_val = host_call(
+        host_call(
             self.request.id.as_str(),
             "ctx",
             "CallExternalBitcode",
             &call_val,
-        )?;
-        let j_res: serde_json::Value = serde_json::from_slice(&call_ret_val)?;
-        if !j_res.is_object() {
-            return Ok(call_ret_val);
-        }
-        match j_res.get("result") {
-            Some(x) => {
-                let r = serde_json::to_vec(&x)?;
-                Ok(r)
-            }
-            None => match j_res.get("error") {
-                Some(x) => {
-                    let r = serde_json::to_vec(&x)?;
-                    Ok(r)
-                }
-                None => Ok(call_ret_val),
-            },
-        }
-    }
-
-    /// close_stream closes the fabric stream
-    /// - sid:    the sream id (returned from one of the new_file_stream or new_stream)
-    ///
-    /// Returns the checksum as hex-encoded string
-    ///
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/b6a5e5b79022d52138b29aa1779b44f29f65ef51/samples/external/src/lib.rs#L60)
-    ///
-    pub fn close_stream(&'a self, sid: String) -> CallResult {
-        self.call_function("CloseStream", json!({ "stream_id": sid }), "ctx")
-    }
-
-    /// new_stream creates a new fabric bitcode stream.
-    /// # Returns
-    /// * output [u8] of format `{"stream_id" : id}` where id is a string
-    ///
-    /// [Example](https://github.com/eluv-io/elv-wasm/blob/b6a5e5b79022d52138b29aa1779b44f29f65ef51/samples/external/src/lib.rs#L57)
-    ///
-    pub fn new_stream(&'a self) -> CallResult {
-        let v = json!({});
-        self.call_function("NewStream", v, "ctx")
+        )
     }
 
-    /// new_file_stream creates a new fabric file
-    /// output [u8] of format where id and path are strings
-    /// {
-    ///   "stream_id": id,
-    ///   "file_name": path
-    /// }
-    pub fn new_file_stream(&'a self) -> CallResult {
-        let v = json!({});
-        self.call_function("NewFileStream", v, "ctx")
+    /// call_external_bitcode_typed calls [BitcodeContext::call_external_bitcode] decoding its result into `T`
+    /// # Arguments
+    /// see [BitcodeContext::call_external_bitcode]
+    pub fn call_external_bitcode_typed<T: DeserializeOwned>(
+        &'a self,
+        function: &str,
+        args: &serde_json::Value,
+        object_hash: &str,
+        code_part_hash: &str,
+    ) -> Result<T, ErrorKinds> {
+        decode_result(
+            "CallExternalBitcode",
+            self.call_external_bitcode(function, args, object_hash, code_part_hash),
+        )
     }
 
     /// q_download_file : downloads the file stored  at the fabric file location path for some content
+    /// The whole file is returned in memory, large files should be streamed with
+    /// [BitcodeContext::q_download_file_reader] instead
     /// # Arguments
     /// *  `path` : fabric file location in the content
-    /// *  `hash_or_token` : hash for the content containing the file
+    /// *  `hash_or_token` : hash or write token for the content containing the file
     ///
-    pub fn q_download_file(&'a mut self, path: &str, hash_or_token: &str) -> CallResult {
+    pub fn q_download_file(&'a mut self, path: &str, hash_or_token: &HashOrToken) -> CallResult {
         self.log_debug(&format!(
             "q_download_file path={path} token={hash_or_token}"
         ))?;
-        let stream_main: NewStreamResult = self.new_stream().try_into()?;
+        let stream_main: NewStreamResult = self.new_stream_raw().try_into()?;
         let sid = stream_main.stream_id.clone();
         if stream_main.stream_id.is_empty() {
             return self.make_error_with_kind(ErrorKinds::IO(format!(
@@ -423,13 +303,7 @@ impl<'a> BitcodeContext {
         defer! {
             let _ = self.close_stream(sid.clone());
         }
-        let j = json!({
-          "stream_id" : &sid,
-          "path" : path,
-          "hash_or_token": hash_or_token,
-        });
-
-        let v: serde_json::Value = match self.call_function("QFileToStream", j, "core") {
+        let v: serde_json::Value = match self.q_file_to_stream(&sid, path, hash_or_token) {
             Err(e) => {
                 return self.make_error_with_kind(ErrorKinds::NotExist(format!(
                     "QFileToStream failed path={path}, hot={hash_or_token} sid={} e={e}",
@@ -460,37 +334,28 @@ impl<'a> BitcodeContext {
     /// *  `path` : fabric file location
     /// *  `mime` : MIME type to store the data as (eg gif)
     ///
+    /// Data produced in chunks may be streamed to the file with [BitcodeContext::create_file_writer]
+    ///
     pub fn q_upload_file(
         &'a mut self,
-        qwt: &str,
+        qwt: &QWriteToken,
         input_data: &[u8],
         path: &str,
         mime: &str,
     ) -> CallResult {
-        let sid = self.new_file_stream()?;
+        let sid = self.new_file_stream_raw()?;
         let new_stream: FileStream = serde_json::from_slice(&sid)?;
         defer! {
           let _ = self.close_stream(new_stream.stream_id.clone());
         }
         let ret_s = self.write_stream(new_stream.clone().stream_id.as_str(), input_data)?;
-        let written_map: HashMap<String, String> = serde_json::from_slice(&ret_s)?;
-        let i: i32 = written_map["written"].parse()?;
-        let j = json!({
-          "qwtoken" : qwt,
-          "stream_id": new_stream.stream_id,
-          "path":path,
-          "mime":mime,
-          "size": i,
-        });
-
-        let method = "QCreateFileFromStream";
-        self.call_function(method, j, "core")
-    }
-
-    /// file_stream_size computes the current size of a fabric file stream given its stream name
-    ///     filename : the name of the file steam.  See new_file_stream.
-    pub fn file_stream_size(&'a self, filename: &str) -> CallResult {
-        self.log_debug("file_stream_size")?;
-        self.call_function("FileStreamSize", json!({ "file_name": filename }), "ctx")
+        let written: WriteResult = serde_json::from_slice(&ret_s)?;
+        self.q_create_file_from_stream(
+            &new_stream.stream_id,
+            qwt,
+            path,
+            mime,
+            written.written as i64,
+        )
     }
 }
@@ -9,8 +9,24 @@ use serde_json::json;
 use thiserror::Error;
 use wapc_guest::CallResult;
 
+/// ErrorKinds enumerates the fabric error kinds.  Each kind has a stable numeric [ErrorKinds::code] and
+/// an associated [ErrorKinds::http_status]
+///
+/// | kind           | code | http status |
+/// |----------------|------|-------------|
+/// | Other          | 0    | 500         |
+/// | NotImplemented | 1    | 501         |
+/// | Invalid        | 2    | 400         |
+/// | Permission     | 3    | 403         |
+/// | IO             | 4    | 500         |
+/// | Exist          | 5    | 409         |
+/// | NotExist       | 6    | 404         |
+/// | IsDir          | 7    | 400         |
+/// | NotDir         | 8    | 400         |
+/// | Finalized      | 9    | 409         |
+/// | NotFinalized   | 10   | 409         |
+/// | BadHttpParams  | 11   | 400         |
 #[derive(Error, Debug, Clone, Serialize)]
-#[repr(u8)]
 pub enum ErrorKinds {
     #[error("Other Error : {0}")]
     Other(String),
@@ -38,24 +54,311 @@ pub enum ErrorKinds {
     BadHttpParams(String),
 }
 
-fn discriminant(v: &ErrorKinds) -> u8 {
-    unsafe { *(v as *const ErrorKinds as *const u8) }
+impl ErrorKinds {
+    /// code returns the stable numeric code of the error kind reported as `op` to clients
+    pub fn code(&self) -> u8 {
+        match self {
+            ErrorKinds::Other(_) => 0,
+            ErrorKinds::NotImplemented(_) => 1,
+            ErrorKinds::Invalid(_) => 2,
+            ErrorKinds::Permission(_) => 3,
+            ErrorKinds::IO(_) => 4,
+            ErrorKinds::Exist(_) => 5,
+            ErrorKinds::NotExist(_) => 6,
+            ErrorKinds::IsDir(_) => 7,
+            ErrorKinds::NotDir(_) => 8,
+            ErrorKinds::Finalized(_) => 9,
+            ErrorKinds::NotFinalized(_) => 10,
+            ErrorKinds::BadHttpParams(_) => 11,
+        }
+    }
+
+    /// kind_name returns the name of the error kind e.g. "NotExist"
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            ErrorKinds::Other(_) => "Other",
+            ErrorKinds::NotImplemented(_) => "NotImplemented",
+            ErrorKinds::Invalid(_) => "Invalid",
+            ErrorKinds::Permission(_) => "Permission",
+            ErrorKinds::IO(_) => "IO",
+            ErrorKinds::Exist(_) => "Exist",
+            ErrorKinds::NotExist(_) => "NotExist",
+            ErrorKinds::IsDir(_) => "IsDir",
+            ErrorKinds::NotDir(_) => "NotDir",
+            ErrorKinds::Finalized(_) => "Finalized",
+            ErrorKinds::NotFinalized(_) => "NotFinalized",
+            ErrorKinds::BadHttpParams(_) => "BadHttpParams",
+        }
+    }
+
+    /// http_status returns the http status a client should see for the error kind
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ErrorKinds::Other(_) | ErrorKinds::IO(_) => 500,
+            ErrorKinds::NotImplemented(_) => 501,
+            ErrorKinds::Invalid(_)
+            | ErrorKinds::IsDir(_)
+            | ErrorKinds::NotDir(_)
+            | ErrorKinds::BadHttpParams(_) => 400,
+            ErrorKinds::Permission(_) => 403,
+            ErrorKinds::NotExist(_) => 404,
+            ErrorKinds::Exist(_) | ErrorKinds::Finalized(_) | ErrorKinds::NotFinalized(_) => 409,
+        }
+    }
+
+    /// message returns the description carried by the error kind
+    pub fn message(&self) -> &str {
+        match self {
+            ErrorKinds::Other(m)
+            | ErrorKinds::NotImplemented(m)
+            | ErrorKinds::Invalid(m)
+            | ErrorKinds::Permission(m)
+            | ErrorKinds::IO(m)
+            | ErrorKinds::Exist(m)
+            | ErrorKinds::NotExist(m)
+            | ErrorKinds::IsDir(m)
+            | ErrorKinds::NotDir(m)
+            | ErrorKinds::Finalized(m)
+            | ErrorKinds::NotFinalized(m)
+            | ErrorKinds::BadHttpParams(m) => m,
+        }
+    }
+
+    /// from_code builds the kind with the given stable numeric code (see [ErrorKinds::code])
+    pub fn from_code(code: u64, msg: String) -> ErrorKinds {
+        match code {
+            1 => ErrorKinds::NotImplemented(msg),
+            2 => ErrorKinds::Invalid(msg),
+            3 => ErrorKinds::Permission(msg),
+            4 => ErrorKinds::IO(msg),
+            5 => ErrorKinds::Exist(msg),
+            6 => ErrorKinds::NotExist(msg),
+            7 => ErrorKinds::IsDir(msg),
+            8 => ErrorKinds::NotDir(msg),
+            9 => ErrorKinds::Finalized(msg),
+            10 => ErrorKinds::NotFinalized(msg),
+            11 => ErrorKinds::BadHttpParams(msg),
+            _ => ErrorKinds::Other(msg),
+        }
+    }
+
+    /// from_fabric_kind maps a fabric error kind (e.g. "item does not exist") or an [ErrorKinds] name
+    /// (e.g. "NotExist") to the kind
+    pub fn from_fabric_kind(kind: &str, msg: String) -> ErrorKinds {
+        let k = kind.to_lowercase().replace(['_', '-', ' '], "");
+        if k.contains("notimplemented") {
+            ErrorKinds::NotImplemented(msg)
+        } else if k.contains("badhttpparams") {
+            ErrorKinds::BadHttpParams(msg)
+        } else if k.contains("invalid") {
+            ErrorKinds::Invalid(msg)
+        } else if k.contains("permission") {
+            ErrorKinds::Permission(msg)
+        } else if k.contains("notfinalized") {
+            ErrorKinds::NotFinalized(msg)
+        } else if k.contains("finalized") {
+            ErrorKinds::Finalized(msg)
+        } else if k.contains("notadirectory") || k.contains("notdir") {
+            ErrorKinds::NotDir(msg)
+        } else if k.contains("directory") || k.contains("isdir") {
+            ErrorKinds::IsDir(msg)
+        } else if k.contains("notexist") || k.contains("notfound") {
+            ErrorKinds::NotExist(msg)
+        } else if k.contains("exist") {
+            ErrorKinds::Exist(msg)
+        } else if k == "io" || k.contains("i/o") {
+            ErrorKinds::IO(msg)
+        } else {
+            ErrorKinds::Other(msg)
+        }
+    }
+
+    /// with_data converts the kind into a [FabricError] carrying a structured data payload
+    pub fn with_data(self, data: serde_json::Value) -> FabricError {
+        FabricError::from(self).with_data(data)
+    }
+
+    /// with_cause converts the kind into a [FabricError] caused by another error
+    pub fn with_cause<E: Into<FabricError>>(self, cause: E) -> FabricError {
+        FabricError::from(self).with_cause(cause)
+    }
+}
+
+/// FabricError is an [ErrorKinds] along with an optional structured data payload and the error that
+/// caused it
+/// ```
+/// use elvwasm::ErrorKinds;
+/// use serde_json::json;
+///
+/// let err = ErrorKinds::NotExist("asset not found".to_string())
+///     .with_data(json!({"path" : "/assets/birds.jpg"}))
+///     .with_cause(ErrorKinds::IO("read failed".to_string()));
+/// assert_eq!(err.http_status(), 404);
+/// ```
+#[derive(Error, Debug, Clone, Serialize)]
+#[error("{kind}")]
+pub struct FabricError {
+    pub kind: ErrorKinds,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    pub data: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[source]
+    pub cause: Option<Box<FabricError>>,
+}
+
+impl FabricError {
+    pub fn new(kind: ErrorKinds) -> FabricError {
+        FabricError {
+            kind,
+            data: serde_json::Value::Null,
+            cause: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: serde_json::Value) -> FabricError {
+        self.data = data;
+        self
+    }
+
+    pub fn with_cause<E: Into<FabricError>>(mut self, cause: E) -> FabricError {
+        self.cause = Some(Box::new(cause.into()));
+        self
+    }
+
+    pub fn code(&self) -> u8 {
+        self.kind.code()
+    }
+
+    pub fn http_status(&self) -> u16 {
+        self.kind.http_status()
+    }
+
+    /// from_fabric parses the `error` member of a host reply.  Fabric errors carry a textual `kind`
+    /// (e.g. "item does not exist"), the failing `op` and arbitrary additional fields, while errors relayed
+    /// from other bitcode carry the numeric `op` produced by [make_json_error].  The original error object is
+    /// kept as the data payload and any nested `cause` becomes the cause chain.
+    pub fn from_fabric(err: &serde_json::Value) -> FabricError {
+        let obj = match err.as_object() {
+            Some(o) => o,
+            None => {
+                let msg = match err.as_str() {
+                    Some(s) => s.to_string(),
+                    None => err.to_string(),
+                };
+                return FabricError::new(ErrorKinds::Other(msg));
+            }
+        };
+        let mut data = obj.clone();
+        let cause = data.remove("cause");
+        let msg = ["message", "reason", "desc", "error"]
+            .iter()
+            .find_map(|k| obj.get(*k).and_then(|v| v.as_str()))
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| serde_json::Value::Object(data.clone()).to_string());
+        let kind = match (obj.get("kind").and_then(|k| k.as_str()), obj.get("op")) {
+            (Some(k), _) => ErrorKinds::from_fabric_kind(k, msg),
+            (None, Some(op)) if op.is_u64() => ErrorKinds::from_code(op.as_u64().unwrap_or(0), msg),
+            _ => ErrorKinds::Other(msg),
+        };
+        let mut fe = FabricError::new(kind).with_data(serde_json::Value::Object(data));
+        if let Some(c) = cause.filter(|c| !c.is_null()) {
+            fe = fe.with_cause(FabricError::from_fabric(&c));
+        }
+        fe
+    }
+
+    /// causes iterates the cause chain starting with the immediate cause
+    pub fn causes(&self) -> impl Iterator<Item = &FabricError> {
+        std::iter::successors(self.cause.as_deref(), |c| c.cause.as_deref())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut data = serde_json::Map::new();
+        data.insert("op".to_string(), json!(self.code()));
+        data.insert("desc".to_string(), json!(self.kind));
+        match &self.data {
+            serde_json::Value::Object(m) => {
+                for (k, v) in m {
+                    data.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+            }
+            serde_json::Value::Null => {}
+            other => {
+                data.insert("details".to_string(), other.clone());
+            }
+        }
+        let mut err = json!({
+          "op" : self.code(),
+          "kind" : self.kind.kind_name(),
+          "status" : self.http_status(),
+          "message" : self.kind.message(),
+          "desc" : self.kind,
+          "data" : data,
+        });
+        let causes: Vec<serde_json::Value> = self
+            .causes()
+            .map(|c| {
+                json!({
+                  "op" : c.code(),
+                  "kind" : c.kind.kind_name(),
+                  "message" : c.kind.message(),
+                })
+            })
+            .collect();
+        if !causes.is_empty() {
+            err["cause"] = json!(causes);
+        }
+        err
+    }
+}
+
+impl From<ErrorKinds> for FabricError {
+    fn from(kind: ErrorKinds) -> FabricError {
+        FabricError::new(kind)
+    }
+}
+
+impl From<Box<dyn std::error::Error + Sync + Send>> for FabricError {
+    /// Recovers the [ErrorKinds] or [FabricError] from a boxed error such as the error of a CallResult,
+    /// any other error is reported as [ErrorKinds::Other]
+    fn from(e: Box<dyn std::error::Error + Sync + Send>) -> FabricError {
+        let e = match e.downcast::<FabricError>() {
+            Ok(fe) => return *fe,
+            Err(e) => e,
+        };
+        match e.downcast::<ErrorKinds>() {
+            Ok(k) => FabricError::new(*k),
+            Err(e) => FabricError::new(ErrorKinds::Other(e.to_string())),
+        }
+    }
 }
-/// make_json_error translates the bitcode [ErrorKinds] to an error response to the client
+
+/// make_json_error translates the bitcode [ErrorKinds] or [FabricError] to an error response to the client
 /// # Arguments
 /// * `err` - the error to be translated to a response
 /// * `id` - the jpc request id
-pub fn make_json_error(err: ErrorKinds, id: &str) -> CallResult {
+/// # Returns
+/// utf8 bytes containing json of the form
+/// ```json
+/// {
+///   "error" : {
+///     "op" : 6,
+///     "kind" : "NotExist",
+///     "status" : 404,
+///     "message" : "asset not found",
+///     "desc" : { "NotExist" : "asset not found" },
+///     "data" : { "op" : 6, "desc" : { "NotExist" : "asset not found" }, "path" : "/assets/birds.jpg" },
+///     "cause" : [ { "op" : 4, "kind" : "IO", "message" : "read failed" } ]
+///   },
+///   "jpc" : "1.0",
+///   "id" : "id"
+/// }
+/// ```
+pub fn make_json_error<E: Into<FabricError>>(err: E, id: &str) -> CallResult {
+    let err: FabricError = err.into();
     let msg = json!(
       {
-        "error" :  {
-          "op" : discriminant(&err),
-          "desc" : err,
-          "data" : {
-            "op" : discriminant(&err),
-            "desc" : err,
-          },
-        },
+        "error" : err.to_json(),
         "jpc" : "1.0",
         "id"  : id,
       }
@@ -73,3 +376,76 @@ pub fn make_success_json(msg: &serde_json::Value, id: &str) -> CallResult {
     let v = serde_json::to_vec(&js_ret)?;
     Ok(v)
 }
+
+/// error_kind returns the [ErrorKinds] of an error returned by the bitcode API if it is one
+/// ```
+/// fn do_something(bcc: &elvwasm::BitcodeContext) -> wapc_guest::CallResult {
+///   match bcc.sqmd_get_json("/missing") {
+///     Err(e) if matches!(elvwasm::error_kind(e.as_ref()), Some(elvwasm::ErrorKinds::NotExist(_))) => {
+///       bcc.make_success("nothing here")
+///     }
+///     other => other,
+///   }
+/// }
+/// ```
+pub fn error_kind<'e>(e: &'e (dyn std::error::Error + 'static)) -> Option<&'e ErrorKinds> {
+    if let Some(fe) = e.downcast_ref::<FabricError>() {
+        return Some(&fe.kind);
+    }
+    e.downcast_ref::<ErrorKinds>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_fabric() {
+        let fe = FabricError::from_fabric(&json!({
+          "op" : "QFileToStream",
+          "kind" : "item does not exist",
+          "path" : "/assets/birds.jpg",
+          "cause" : { "kind" : "permission denied", "reason" : "no access" },
+        }));
+        assert!(matches!(fe.kind, ErrorKinds::NotExist(_)));
+        assert_eq!(fe.data["path"], "/assets/birds.jpg");
+        assert!(
+            matches!(fe.cause.as_ref().unwrap().kind, ErrorKinds::Permission(ref m) if m == "no access")
+        );
+
+        let fe = FabricError::from_fabric(&json!({"op" : 9, "desc" : "already done"}));
+        assert!(matches!(fe.kind, ErrorKinds::Finalized(_)));
+        let fe = FabricError::from_fabric(&json!({"kind" : "item is not finalized"}));
+        assert!(matches!(fe.kind, ErrorKinds::NotFinalized(_)));
+        let fe = FabricError::from_fabric(&json!("boom"));
+        assert!(matches!(fe.kind, ErrorKinds::Other(ref m) if m == "boom"));
+    }
+
+    #[test]
+    fn test_error_json() {
+        let err = ErrorKinds::NotExist("asset not found".to_string())
+            .with_data(json!({"path" : "/assets/birds.jpg", "op" : 99}))
+            .with_cause(
+                ErrorKinds::IO("read failed".to_string())
+                    .with_cause(ErrorKinds::Permission("denied".to_string())),
+            );
+        let res: serde_json::Value =
+            serde_json::from_slice(&make_json_error(err, "id1").unwrap()).unwrap();
+        let e = &res["error"];
+        assert_eq!(e["op"], 6);
+        assert_eq!(e["status"], 404);
+        assert_eq!(e["kind"], "NotExist");
+        assert_eq!(e["message"], "asset not found");
+        assert_eq!(e["data"]["op"], 6);
+        assert_eq!(e["data"]["path"], "/assets/birds.jpg");
+        assert_eq!(e["cause"][0]["kind"], "IO");
+        assert_eq!(e["cause"][1]["kind"], "Permission");
+        assert_eq!(e["cause"][1]["op"], 3);
+
+        let boxed: Box<dyn std::error::Error + Sync + Send> =
+            Box::new(ErrorKinds::Permission("denied".to_string()));
+        assert_eq!(FabricError::from(boxed).http_status(), 403);
+        let boxed: Box<dyn std::error::Error + Sync + Send> = "plain".into();
+        assert_eq!(FabricError::from(boxed).code(), 0);
+    }
+}
@@ -1,13 +1,39 @@
 extern crate thiserror;
 extern crate wapc_guest as guest;
 
-use crate::{BitcodeContext, SeekResult};
+use crate::{
+    BitcodeContext, CreatePartResult, ErrorKinds, FabricError, HashOrToken, QPartInfo, QWriteToken,
+    SeekResult,
+};
 
-use std::io::{ErrorKind, Read, SeekFrom};
+#[cfg(any(test, feature = "checksum"))]
+use sha2::Digest;
+use std::io::{BufRead, ErrorKind, Read, SeekFrom, Write};
 
+/// DEFAULT_READ_CHUNK is the number of bytes a [FabricStreamReader] requests from the fabric per host call
+pub const DEFAULT_READ_CHUNK: usize = 256 * 1024;
+
+/// ReaderStats counts the host calls made by a [FabricStreamReader], reads and seeks alike, and the bytes
+/// the reads returned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReaderStats {
+    pub host_calls: usize,
+    pub bytes: usize,
+}
+
+/// FabricStreamReader reads a fabric stream through a read-ahead buffer, so that the many small reads of
+/// decoders and tar readers are served from memory rather than by a host call each.  Reads at least as
+/// large as the chunk size bypass the buffer.
 pub struct FabricStreamReader<'a> {
     stream_id: String,
     bcc: &'a BitcodeContext,
+    /// the number of bytes in the stream when known e.g. the size of a downloaded file, 0 otherwise
+    pub size: usize,
+    close_on_drop: bool,
+    buf: Vec<u8>,
+    pos: usize,
+    chunk_size: usize,
+    stats: ReaderStats,
 }
 
 impl<'a> FabricStreamReader<'a> {
@@ -15,31 +41,94 @@ impl<'a> FabricStreamReader<'a> {
         FabricStreamReader {
             stream_id: sid,
             bcc: bcc_in,
+            size: 0,
+            close_on_drop: false,
+            buf: Vec::new(),
+            pos: 0,
+            chunk_size: DEFAULT_READ_CHUNK,
+            stats: ReaderStats::default(),
+        }
+    }
+
+    /// owned creates a reader that takes ownership of the stream, closing it when the reader is dropped
+    /// # Arguments
+    /// * `sid`-  the stream id (returned from new_stream_raw)
+    /// * `size`-  the number of bytes in the stream, 0 if unknown
+    pub fn owned(sid: String, bcc_in: &'a BitcodeContext, size: usize) -> FabricStreamReader<'a> {
+        let mut reader = FabricStreamReader::new(sid, bcc_in);
+        reader.size = size;
+        reader.close_on_drop = true;
+        reader
+    }
+
+    /// with_chunk_size sets the number of bytes requested per host call, [DEFAULT_READ_CHUNK] by default
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> FabricStreamReader<'a> {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// stats returns the host calls made and the bytes read from the fabric so far
+    pub fn stats(&self) -> ReaderStats {
+        self.stats
+    }
+
+    fn read_host(&mut self, len: usize) -> std::io::Result<Vec<u8>> {
+        self.stats.host_calls += 1;
+        let read_bytes = self
+            .bcc
+            .read_stream(self.stream_id.clone(), len)
+            .map_err(to_io_error)?;
+        self.stats.bytes += read_bytes.len();
+        Ok(read_bytes)
+    }
+}
+
+impl Drop for FabricStreamReader<'_> {
+    fn drop(&mut self) {
+        if self.close_on_drop {
+            let _ = self.bcc.close_stream(self.stream_id.clone());
         }
     }
 }
 
 impl Read for FabricStreamReader<'_> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let read_bytes = match self.bcc.read_stream(self.stream_id.clone(), buf.len()) {
-            Ok(rb) => rb,
-            Err(e) => {
-                let _ = self.bcc.log_error(&format!("Error reading stream: {e}"));
-                Vec::new()
-            }
-        };
-        if read_bytes.is_empty() {
+        // a zero length Reader call reads the whole stream
+        if buf.is_empty() {
             return Ok(0);
         }
-        let len = std::cmp::min(buf.len(), read_bytes.len());
-        buf[..len].copy_from_slice(&read_bytes[..len]);
-        let _ = self
-            .bcc
-            .log_debug(&format!("Read {len} bytes in FabricStreamReader"));
+        if self.pos >= self.buf.len() && buf.len() >= self.chunk_size {
+            let read_bytes = self.read_host(buf.len())?;
+            let len = std::cmp::min(buf.len(), read_bytes.len());
+            buf[..len].copy_from_slice(&read_bytes[..len]);
+            return Ok(len);
+        }
+        let available = self.fill_buf()?;
+        let len = std::cmp::min(buf.len(), available.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
         Ok(len)
     }
 }
 
+impl BufRead for FabricStreamReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos >= self.buf.len() {
+            self.buf = self.read_host(self.chunk_size)?;
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = std::cmp::min(self.pos + amt, self.buf.len());
+    }
+}
+
 //FabricStreamWriter is a struct that implements the Write trait
 //The struct is used to write the image bits to the qfab based stream
 // The is no buffer in the struct as the BufWriter will write immediately to "fos" of qfab's context
@@ -48,27 +137,51 @@ pub struct FabricStreamWriter<'a> {
     pub bcc: &'a BitcodeContext,
     stream_id: String,
     pub size: usize,
+    position: u64,
+    len: u64,
 }
 
-impl FabricStreamWriter<'_> {
-    pub fn new(bcc: &BitcodeContext, sid: String, sz: usize) -> FabricStreamWriter<'_> {
+impl<'a> FabricStreamWriter<'a> {
+    pub fn new(bcc: &'a BitcodeContext, sid: String, sz: usize) -> FabricStreamWriter<'a> {
         FabricStreamWriter {
             bcc,
             stream_id: sid,
             size: sz,
+            position: 0,
+            len: 0,
         }
     }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// len returns the length of the stream as written through this writer, the furthest position
+    /// written or sought to.  No host call is made.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// batched wraps the writer into a [BatchedStreamWriter] issuing host writes of batch_size bytes
+    pub fn batched(self, batch_size: usize) -> BatchedStreamWriter<'a> {
+        BatchedStreamWriter::new(self, batch_size)
+    }
 }
 impl std::io::Write for FabricStreamWriter<'_> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
-        match self.bcc.write_stream(&self.stream_id, buf) {
-            Ok(s) => {
-                let w: crate::WritePartResult = serde_json::from_slice(&s)?;
-                self.size += w.written;
-                Ok(w.written)
-            }
-            Err(e) => Err(std::io::Error::new(ErrorKind::Other, e)),
-        }
+        let s = self
+            .bcc
+            .write_stream(&self.stream_id, buf)
+            .map_err(to_io_error)?;
+        let w: crate::WritePartResult = serde_json::from_slice(&s)?;
+        self.size += w.written;
+        self.position += w.written as u64;
+        self.len = self.len.max(self.position);
+        Ok(w.written)
     }
 
     fn flush(&mut self) -> Result<(), std::io::Error> {
@@ -77,29 +190,973 @@ impl std::io::Write for FabricStreamWriter<'_> {
     }
 }
 
+/// DEFAULT_WRITE_BATCH is the number of bytes a [BatchedStreamWriter] sends to the fabric per host call
+pub const DEFAULT_WRITE_BATCH: usize = 1024 * 1024;
+
+/// WriterStats counts the host calls made by a [BatchedStreamWriter] and the bytes they wrote
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriterStats {
+    pub host_calls: usize,
+    pub bytes: usize,
+}
+
+/// BatchedStreamWriter coalesces the many small writes of encoders and tar builders into host writes of
+/// a fixed batch size.  Writes at least as large as the batch bypass the buffer and vectored writes gather
+/// all their slices into the batch, so a tar header, its data and its padding cost a single host call.
+/// Buffered bytes are written by [std::io::Write::flush], [BatchedStreamWriter::into_inner] or when the
+/// writer is dropped, where errors are ignored.
+/// ```ignore
+/// let mut fw = FabricStreamWriter::new(bcc, "fos".to_string(), 0).batched(DEFAULT_WRITE_BATCH);
+/// let mut a = tar::Builder::new(&mut fw);
+/// ...
+/// let fw = fw.into_inner()?;
+/// bcc.callback(200, "application/x-tar", fw.size)?;
+/// ```
+pub struct BatchedStreamWriter<'a> {
+    writer: FabricStreamWriter<'a>,
+    buf: Vec<u8>,
+    batch_size: usize,
+    stats: WriterStats,
+}
+
+impl<'a> BatchedStreamWriter<'a> {
+    pub fn new(writer: FabricStreamWriter<'a>, batch_size: usize) -> BatchedStreamWriter<'a> {
+        let batch_size = batch_size.max(1);
+        BatchedStreamWriter {
+            writer,
+            buf: Vec::with_capacity(batch_size),
+            batch_size,
+            stats: WriterStats::default(),
+        }
+    }
+
+    /// stats returns the host calls made and the bytes written to the fabric so far
+    pub fn stats(&self) -> WriterStats {
+        self.stats
+    }
+
+    /// buffered returns the bytes not yet written to the fabric
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get_ref(&self) -> &FabricStreamWriter<'a> {
+        &self.writer
+    }
+
+    /// into_inner writes the buffered bytes and returns the underlying writer
+    pub fn into_inner(mut self) -> std::io::Result<FabricStreamWriter<'a>> {
+        self.flush_buf()?;
+        let bcc = self.writer.bcc;
+        Ok(std::mem::replace(
+            &mut self.writer,
+            FabricStreamWriter::new(bcc, String::new(), 0),
+        ))
+    }
+
+    /// write_once makes a single host write of data returning the bytes the fabric accepted, at least one
+    fn write_once(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n = self.writer.write(data)?;
+        self.stats.host_calls += 1;
+        self.stats.bytes += n;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                ErrorKind::WriteZero,
+                format!("stream {} accepted no bytes", self.writer.stream_id),
+            ));
+        }
+        Ok(n)
+    }
+
+    /// flush_buf writes the buffered bytes, those the fabric did not accept stay buffered on error
+    fn flush_buf(&mut self) -> std::io::Result<()> {
+        while !self.buf.is_empty() {
+            let buf = std::mem::take(&mut self.buf);
+            let res = self.write_once(&buf);
+            self.buf = buf;
+            let n = res?;
+            self.buf.drain(..n);
+        }
+        Ok(())
+    }
+
+    /// push buffers data or writes it directly when it is at least a batch, returning the bytes taken.  An
+    /// error is only returned when none were taken.
+    fn push(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.buf.len() + data.len() > self.batch_size {
+            self.flush_buf()?;
+        }
+        if data.len() < self.batch_size {
+            self.buf.extend_from_slice(data);
+            return Ok(data.len());
+        }
+        let mut written = 0;
+        while written < data.len() {
+            match self.write_once(&data[written..]) {
+                Ok(n) => written += n,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl std::io::Write for BatchedStreamWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.push(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> Result<usize, std::io::Error> {
+        // bytes already taken are reported rather than an error, which the next write will surface
+        let mut written = 0;
+        for b in bufs {
+            match self.push(b) {
+                Ok(n) => {
+                    written += n;
+                    if n < b.len() {
+                        break;
+                    }
+                }
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.flush_buf()
+    }
+}
+
+impl Drop for BatchedStreamWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.flush_buf();
+    }
+}
+
+/// FabricFileWriter writes a new fabric file through a file stream.  The bytes written are counted and
+/// [FabricFileWriter::finish] creates the file at its path in the content once the writing is done.  The
+/// stream is closed when the writer is dropped, whether or not it was finished.
+pub struct FabricFileWriter<'a> {
+    writer: FabricStreamWriter<'a>,
+    qwt: QWriteToken,
+    path: String,
+    mime: String,
+}
+
+impl<'a> FabricFileWriter<'a> {
+    pub fn new(
+        bcc: &'a BitcodeContext,
+        sid: String,
+        qwt: &QWriteToken,
+        path: &str,
+        mime: &str,
+    ) -> FabricFileWriter<'a> {
+        FabricFileWriter {
+            writer: FabricStreamWriter::new(bcc, sid, 0),
+            qwt: qwt.clone(),
+            path: path.to_string(),
+            mime: mime.to_string(),
+        }
+    }
+
+    /// size returns the number of bytes written so far
+    pub fn size(&self) -> usize {
+        self.writer.size
+    }
+
+    /// finish creates the fabric file from the bytes written
+    /// # Returns
+    /// the [QPartInfo] of the part holding the file
+    pub fn finish(self) -> Result<QPartInfo, ErrorKinds> {
+        self.writer.bcc.q_create_file_from_stream_typed(
+            &self.writer.stream_id,
+            &self.qwt,
+            &self.path,
+            &self.mime,
+            self.writer.size as i64,
+        )
+    }
+}
+
+impl std::io::Write for FabricFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for FabricFileWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.writer.bcc.close_stream(self.writer.stream_id.clone());
+    }
+}
+
+#[cfg(any(test, feature = "checksum"))]
+enum PartHasher {
+    Md5(md5::Md5),
+    Sha256(sha2::Sha256),
+}
+
+#[cfg(any(test, feature = "checksum"))]
+impl PartHasher {
+    fn new(method: &str) -> Result<PartHasher, ErrorKinds> {
+        match method.to_uppercase().as_str() {
+            "MD5" => Ok(PartHasher::Md5(md5::Md5::new())),
+            "SHA256" => Ok(PartHasher::Sha256(sha2::Sha256::new())),
+            m => Err(ErrorKinds::Invalid(format!(
+                "unsupported checksum method {m}"
+            ))),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            PartHasher::Md5(h) => h.update(data),
+            PartHasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    fn hex(&self) -> String {
+        let sum = match self {
+            PartHasher::Md5(h) => h.clone().finalize().to_vec(),
+            PartHasher::Sha256(h) => h.clone().finalize().to_vec(),
+        };
+        sum.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+/// PartWriter builds a new content part incrementally through a bitcode stream.  [PartWriter::finish]
+/// creates the part from the bytes written.  With the `checksum` feature the writer may compute the
+/// checksum of the part as it is written, to be verified against [BitcodeContext::q_checksum_part].  The
+/// stream is closed when the writer is dropped.
+pub struct PartWriter<'a> {
+    writer: FabricStreamWriter<'a>,
+    qwt: QWriteToken,
+    #[cfg(any(test, feature = "checksum"))]
+    hasher: Option<PartHasher>,
+}
+
+impl<'a> PartWriter<'a> {
+    pub fn new(bcc: &'a BitcodeContext, sid: String, qwt: &QWriteToken) -> PartWriter<'a> {
+        PartWriter {
+            writer: FabricStreamWriter::new(bcc, sid, 0),
+            qwt: qwt.clone(),
+            #[cfg(any(test, feature = "checksum"))]
+            hasher: None,
+        }
+    }
+
+    /// with_checksum computes the checksum of the bytes written with sum_method ("MD5" or "SHA256")
+    #[cfg(any(test, feature = "checksum"))]
+    pub fn with_checksum(mut self, sum_method: &str) -> Result<PartWriter<'a>, ErrorKinds> {
+        self.hasher = Some(PartHasher::new(sum_method)?);
+        Ok(self)
+    }
+
+    /// checksum returns the hex-encoded checksum of the bytes written so far, None unless requested with
+    /// [PartWriter::with_checksum]
+    #[cfg(any(test, feature = "checksum"))]
+    pub fn checksum(&self) -> Option<String> {
+        self.hasher.as_ref().map(|h| h.hex())
+    }
+
+    /// size returns the number of bytes written so far
+    pub fn size(&self) -> usize {
+        self.writer.size
+    }
+
+    /// finish creates the part from the bytes written
+    pub fn finish(self) -> Result<CreatePartResult, ErrorKinds> {
+        self.writer
+            .bcc
+            .q_create_part_from_stream_typed(&self.qwt, &self.writer.stream_id)
+    }
+}
+
+impl std::io::Write for PartWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let written = self.writer.write(buf)?;
+        #[cfg(any(test, feature = "checksum"))]
+        if let Some(h) = self.hasher.as_mut() {
+            h.update(&buf[..written.min(buf.len())]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for PartWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.writer.bcc.close_stream(self.writer.stream_id.clone());
+    }
+}
+
+/// FabricStream owns a bitcode stream created by [BitcodeContext::new_stream] or
+/// [BitcodeContext::new_file_stream] and closes it when dropped, replacing the
+/// `defer! { close_stream(...) }` blocks around [BitcodeContext::new_stream_raw].  Readers and writers of
+/// the stream borrow the handle so they cannot outlive it.
+/// ```ignore
+/// let stream = bcc.new_stream()?;
+/// bcc.write_part_to_stream(stream.stream_id().to_string(), &qphash, &qhot, 0, -1, true)?;
+/// std::io::copy(&mut stream.reader(), &mut fos)?;
+/// ```
+pub struct FabricStream<'a> {
+    bcc: &'a BitcodeContext,
+    stream_id: String,
+    file_name: Option<String>,
+    closed: bool,
+}
+
+impl<'a> FabricStream<'a> {
+    pub fn new(
+        bcc: &'a BitcodeContext,
+        sid: String,
+        file_name: Option<String>,
+    ) -> FabricStream<'a> {
+        FabricStream {
+            bcc,
+            stream_id: sid,
+            file_name,
+            closed: false,
+        }
+    }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// file_name returns the fabric file name of a file stream, None for other streams
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// reader returns a buffered reader of the stream
+    pub fn reader(&self) -> FabricStreamReader<'_> {
+        FabricStreamReader::new(self.stream_id.clone(), self.bcc)
+    }
+
+    /// writer returns a writer to the stream
+    pub fn writer(&self) -> FabricStreamWriter<'_> {
+        FabricStreamWriter::new(self.bcc, self.stream_id.clone(), 0)
+    }
+
+    /// size returns the current size of a file stream, see [BitcodeContext::file_stream_size]
+    pub fn size(&self) -> Result<usize, ErrorKinds> {
+        match &self.file_name {
+            Some(name) => Ok(self.bcc.file_stream_size_typed(name)?.file_size),
+            None => Err(ErrorKinds::Invalid(format!(
+                "stream {} is not a file stream",
+                self.stream_id
+            ))),
+        }
+    }
+
+    /// close closes the stream
+    /// # Returns
+    /// the checksum of the stream as hex-encoded string
+    pub fn close(mut self) -> Result<String, ErrorKinds> {
+        self.closed = true;
+        self.bcc.close_stream_typed(self.stream_id.clone())
+    }
+}
+
+impl Drop for FabricStream<'_> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.bcc.close_stream(self.stream_id.clone());
+        }
+    }
+}
+
+/// io_error_kind maps a fabric error kind to the closest [std::io::ErrorKind]
+fn io_error_kind(kind: &ErrorKinds) -> ErrorKind {
+    match kind {
+        ErrorKinds::NotExist(_) => ErrorKind::NotFound,
+        ErrorKinds::Exist(_) => ErrorKind::AlreadyExists,
+        ErrorKinds::Permission(_) => ErrorKind::PermissionDenied,
+        ErrorKinds::Invalid(_) | ErrorKinds::BadHttpParams(_) => ErrorKind::InvalidInput,
+        ErrorKinds::NotImplemented(_) => ErrorKind::Unsupported,
+        _ => ErrorKind::Other,
+    }
+}
+
+/// to_io_error converts a failed stream host call into an [std::io::Error] of the matching kind.  The
+/// [FabricError] is kept as the inner error so callers may recover the fabric error kind with
+/// `e.get_ref().and_then(|e| e.downcast_ref::<FabricError>())`
+fn to_io_error(e: Box<dyn std::error::Error + Sync + Send>) -> std::io::Error {
+    let mut fe = FabricError::from(e);
+    // the fabric reports failed stream operations with the text of its json error
+    if let ErrorKinds::Other(msg) = &fe.kind {
+        if let Ok(v) = serde_json::from_str::<serde_json::Value>(msg) {
+            if v.is_object() {
+                fe = FabricError::from_fabric(v.get("error").unwrap_or(&v));
+            }
+        }
+    }
+    std::io::Error::new(io_error_kind(&fe.kind), fe)
+}
+
 fn seek_impl(bcc: &BitcodeContext, pos: SeekFrom, stream_id: &str) -> Result<u64, std::io::Error> {
     let (offset, whence) = match pos {
         SeekFrom::Start(offset) => (offset as i64, 0),
         SeekFrom::Current(offset) => (offset, 1),
         SeekFrom::End(offset) => (offset, 2),
     };
-    match bcc.seek_stream(stream_id, offset, whence) {
-        Ok(x) => {
-            let sr: SeekResult = serde_json::from_slice(&x)?;
-            Ok(sr.offset)
-        }
-        Err(e) => Err(std::io::Error::new(ErrorKind::Other, e)),
-    }
+    let x = bcc
+        .seek_stream(stream_id, offset, whence)
+        .map_err(to_io_error)?;
+    let sr: SeekResult = serde_json::from_slice(&x)?;
+    Ok(sr.offset)
 }
 
 impl std::io::Seek for FabricStreamWriter<'_> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
-        seek_impl(self.bcc, pos, "fos")
+        self.position = seek_impl(self.bcc, pos, &self.stream_id)?;
+        self.len = self.len.max(self.position);
+        Ok(self.position)
+    }
+
+    /// stream_position returns the position tracked by the writer without a host seek
+    fn stream_position(&mut self) -> Result<u64, std::io::Error> {
+        Ok(self.position)
     }
 }
 
 impl std::io::Seek for FabricStreamReader<'_> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
-        seek_impl(self.bcc, pos, &self.stream_id)
+        // the fabric stream is ahead of the reader by the bytes still buffered
+        let pos = match pos {
+            SeekFrom::Current(offset) => {
+                SeekFrom::Current(offset - (self.buf.len() - self.pos) as i64)
+            }
+            p => p,
+        };
+        self.stats.host_calls += 1;
+        let offset = seek_impl(self.bcc, pos, &self.stream_id)?;
+        self.buf.clear();
+        self.pos = 0;
+        Ok(offset)
+    }
+}
+
+impl<'a> BitcodeContext {
+    /// q_download_file_reader : streams the file stored at the fabric file location path for some content
+    /// The file is copied to a new stream which the returned reader reads chunk by chunk, so large files
+    /// are never held in memory as a whole.  The stream is closed when the reader is dropped.
+    /// # Arguments
+    /// *  `path` : fabric file location in the content
+    /// *  `hash_or_token` : hash or write token for the content containing the file
+    /// ```ignore
+    /// let mut reader = bcc.q_download_file_reader("assets/big.mp4", &qhot)?;
+    /// let mut fw = FabricStreamWriter::new(bcc, "fos".to_string(), 0);
+    /// std::io::copy(&mut reader, &mut fw)?;
+    /// ```
+    pub fn q_download_file_reader(
+        &'a self,
+        path: &str,
+        hash_or_token: &HashOrToken,
+    ) -> Result<FabricStreamReader<'a>, ErrorKinds> {
+        let stream = self.new_stream_typed()?;
+        if stream.stream_id.is_empty() {
+            return Err(ErrorKinds::IO("Unable to create stream".to_string()));
+        }
+        // the reader owns the stream from here on, closing it should the copy fail
+        let mut reader = FabricStreamReader::owned(stream.stream_id, self, 0);
+        let res = self
+            .q_file_to_stream_typed(reader.stream_id(), path, hash_or_token)
+            .map_err(|e| {
+                ErrorKinds::NotExist(format!(
+                    "QFileToStream failed path={path}, hot={hash_or_token} e={e}"
+                ))
+            })?;
+        reader.size = res.written;
+        Ok(reader)
+    }
+
+    /// new_stream creates a new fabric bitcode stream closed when the returned handle is dropped, see
+    /// [BitcodeContext::new_stream_raw]
+    pub fn new_stream(&'a self) -> Result<FabricStream<'a>, ErrorKinds> {
+        let stream = self.new_stream_typed()?;
+        if stream.stream_id.is_empty() {
+            return Err(ErrorKinds::IO("Unable to create stream".to_string()));
+        }
+        Ok(FabricStream::new(self, stream.stream_id, None))
+    }
+
+    /// new_file_stream creates a new fabric file closed when the returned handle is dropped, see
+    /// [BitcodeContext::new_file_stream_raw]
+    pub fn new_file_stream(&'a self) -> Result<FabricStream<'a>, ErrorKinds> {
+        let stream = self.new_file_stream_typed()?;
+        if stream.stream_id.is_empty() {
+            return Err(ErrorKinds::IO("Unable to create stream".to_string()));
+        }
+        Ok(FabricStream::new(
+            self,
+            stream.stream_id,
+            Some(stream.file_name),
+        ))
+    }
+
+    /// create_file_writer : creates a writer storing its data as a new fabric file
+    /// The data may be written in chunks of any size, [FabricFileWriter::finish] stores it at path.
+    /// # Arguments
+    /// * `qwt` : a fabric write token
+    /// *  `path` : fabric file location
+    /// *  `mime` : MIME type to store the data as (eg gif)
+    /// ```ignore
+    /// let mut fw = bcc.create_file_writer(&qwt, "assets/out.tar", "application/x-tar")?;
+    /// std::io::copy(&mut reader, &mut fw)?;
+    /// let info = fw.finish()?;
+    /// ```
+    pub fn create_file_writer(
+        &'a self,
+        qwt: &QWriteToken,
+        path: &str,
+        mime: &str,
+    ) -> Result<FabricFileWriter<'a>, ErrorKinds> {
+        let stream = self.new_file_stream_typed()?;
+        if stream.stream_id.is_empty() {
+            return Err(ErrorKinds::IO("Unable to create stream".to_string()));
+        }
+        Ok(FabricFileWriter::new(
+            self,
+            stream.stream_id,
+            qwt,
+            path,
+            mime,
+        ))
+    }
+
+    /// create_part_writer : creates a writer building a new part in the content of qwt
+    /// # Arguments
+    /// * `qwt` : a fabric write token
+    /// ```ignore
+    /// let mut pw = bcc.create_part_writer(&qwt)?.with_checksum("SHA256")?;
+    /// std::io::copy(&mut reader, &mut pw)?;
+    /// let sum = pw.checksum();
+    /// let part = pw.finish()?;
+    /// ```
+    pub fn create_part_writer(&'a self, qwt: &QWriteToken) -> Result<PartWriter<'a>, ErrorKinds> {
+        let stream = self.new_stream_typed()?;
+        if stream.stream_id.is_empty() {
+            return Err(ErrorKinds::IO("Unable to create stream".to_string()));
+        }
+        Ok(PartWriter::new(self, stream.stream_id, qwt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FabricStreamReader, FabricStreamWriter, ReaderStats};
+    use crate::bccontext_testing::{install_host, ScriptedHost};
+    use crate::{BitcodeContext, ErrorKinds, FabricError, HashOrToken, QWriteToken};
+    use guest::CallResult;
+    use serde_json::json;
+    use std::io::{BufRead, ErrorKind, IoSlice, Read, Seek, SeekFrom, Write};
+
+    const QHASH: &str =
+        "hq__CDkDyULmCVq6RwbwSBG2DVCReuGmQy14PsjtxYMNVDu9i7qHgnstvyzQiGhki8S8sRJR4wGGUz";
+    const QWT: &str =
+        "tqw__HSZanpatHeCTnKisyqwKUS8BEN4hErCxtxJe9VyqAy8q75bHcDBUSoMBbmRPvyr4yDJp4PCUMvFw1BBoLV2";
+    const QPHASH: &str = "hqp_4MyXwriZzDG25deHUFUNCDkmi6Az";
+
+    fn written(n: usize) -> Vec<u8> {
+        json!({ "written": n }).to_string().into_bytes()
+    }
+
+    #[test]
+    fn test_download_file_reader() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond("ctx", "NewStream", json!({ "stream_id": "sid1" }))
+            .respond("core", "QFileToStream", json!({ "written": 17 }))
+            .respond_error(
+                "core",
+                "QFileToStream",
+                ErrorKinds::NotExist("missing.txt".to_string()),
+            )
+            .respond_raw("*", "Reader", b"robin\nwren\nheron\n")
+            .respond_raw("*", "Reader", b"")
+            .respond("ctx", "CloseStream", json!(""));
+        let _guard = host.install();
+        let hash: HashOrToken = QHASH.parse().unwrap();
+        {
+            let mut reader = bcc
+                .q_download_file_reader("assets/birds.txt", &hash)
+                .unwrap();
+            assert_eq!(reader.size, 17);
+            let mut chunk = [0u8; 6];
+            reader.read_exact(&mut chunk).unwrap();
+            assert_eq!(&chunk, b"robin\n");
+            let mut rest = String::new();
+            reader.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, "wren\nheron\n");
+            host.assert_not_called("ctx", "CloseStream");
+        }
+        let copy = &host.calls_to("core", "QFileToStream")[0];
+        assert_eq!(copy.params()["stream_id"], "sid1");
+        assert_eq!(copy.params()["hash_or_token"], QHASH);
+        assert_eq!(host.calls_to("ctx", "CloseStream").len(), 1);
+
+        // the stream is closed when the copy fails
+        assert!(bcc.q_download_file_reader("missing.txt", &hash).is_err());
+        let closed = host.calls_to("ctx", "CloseStream");
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[1].params()["stream_id"], "sid1");
+    }
+
+    #[test]
+    fn test_buffered_reader() {
+        let bcc = BitcodeContext::default();
+        let mut host = ScriptedHost::new();
+        for chunk in [
+            &b"robin\nwr"[..],
+            b"en\nheron",
+            b"\n",
+            b"",
+            b"robin\nwr",
+            b"wren\nheron\n",
+        ] {
+            host = host.respond_raw("*", "Reader", chunk);
+        }
+        let host = host
+            .respond_raw("*", "Seek", br#"{"offset" : 0}"#)
+            .respond_raw("*", "Seek", br#"{"offset" : 6}"#);
+        let _guard = host.install();
+        let mut reader = FabricStreamReader::new("sid1".to_string(), &bcc).with_chunk_size(8);
+        let lines: Vec<String> = (&mut reader).lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["robin", "wren", "heron"]);
+        // 8 + 8 + 1 bytes then the empty read at the end of the stream
+        assert_eq!(
+            reader.stats(),
+            ReaderStats {
+                host_calls: 4,
+                bytes: 17
+            }
+        );
+        assert_eq!(host.calls_to("sid1", "Reader")[0].params()["len"], 8);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut first = [0u8; 3];
+        reader.read_exact(&mut first).unwrap();
+        assert_eq!(&first, b"rob");
+        // the 5 bytes still buffered are taken back from the fabric position
+        assert_eq!(reader.seek(SeekFrom::Current(3)).unwrap(), 6);
+        let seek = &host.calls_to("sid1", "Seek")[1];
+        assert_eq!(seek.params(), json!({ "offset": -2, "whence": 1 }));
+        let mut big = [0u8; 16];
+        assert_eq!(reader.read(&mut big).unwrap(), 11);
+        assert_eq!(&big[..11], b"wren\nheron\n");
+        assert_eq!(host.calls_to("sid1", "Reader")[5].params()["len"], 16);
+        // two seeks and two reads since the lines were read
+        assert_eq!(
+            reader.stats(),
+            ReaderStats {
+                host_calls: 8,
+                bytes: 36
+            }
+        );
+    }
+
+    #[test]
+    fn test_writer_seek() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond_raw("*", "Write", &written(11))
+            .respond_raw("*", "Write", &written(1))
+            .respond_raw("*", "Seek", br#"{"offset" : 0}"#);
+        let _guard = host.install();
+        let mut writer = FabricStreamWriter::new(&bcc, "sid1".to_string(), 0);
+        writer.write_all(b"hello world").unwrap();
+        assert_eq!(writer.seek(SeekFrom::Start(0)).unwrap(), 0);
+        writer.write_all(b"J").unwrap();
+        assert_eq!(writer.stream_position().unwrap(), 1);
+        assert_eq!(writer.len(), 11);
+        assert_eq!(writer.size, 12);
+
+        host.assert_calls(&[("sid1", "Write"), ("sid1", "Seek"), ("sid1", "Write")]);
+        assert_eq!(
+            host.calls_to("sid1", "Seek")[0].params(),
+            json!({ "offset": 0, "whence": 0 })
+        );
+        assert_eq!(host.calls_to("sid1", "Write")[1].payload, b"J");
+    }
+
+    #[test]
+    fn test_stream_handle() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond("ctx", "NewStream", json!({ "stream_id": "sid1" }))
+            .respond(
+                "ctx",
+                "NewFileStream",
+                json!({ "stream_id": "sid2", "file_name": "file2" }),
+            )
+            .respond_raw("*", "Write", &written(6))
+            .respond("ctx", "FileStreamSize", json!({ "file_size": 6 }))
+            .respond_raw("*", "Seek", br#"{"offset" : 0}"#)
+            .respond_raw("*", "Reader", b"merlin")
+            .respond_raw("*", "Reader", b"")
+            .respond(
+                "ctx",
+                "CloseStream",
+                json!("5d41402abc4b2a76b9719d911017c592"),
+            );
+        let _guard = host.install();
+        {
+            let stream = bcc.new_stream().unwrap();
+            stream.writer().write_all(b"falcon").unwrap();
+            assert!(stream.size().is_err());
+            host.assert_not_called("ctx", "CloseStream");
+        }
+        let closed = host.calls_to("ctx", "CloseStream");
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].params()["stream_id"], "sid1");
+
+        let file = bcc.new_file_stream().unwrap();
+        assert_eq!(file.file_name(), Some("file2"));
+        let mut writer = file.writer();
+        writer.write_all(b"merlin").unwrap();
+        assert_eq!(file.size().unwrap(), 6);
+        assert_eq!(
+            host.calls_to("ctx", "FileStreamSize")[0].params()["file_name"],
+            "file2"
+        );
+        writer.seek(SeekFrom::Start(0)).unwrap();
+        let mut merlin = String::new();
+        file.reader().read_to_string(&mut merlin).unwrap();
+        assert_eq!(merlin, "merlin");
+        let sum = file.close().unwrap();
+        assert_eq!(sum, "5d41402abc4b2a76b9719d911017c592");
+        // a closed stream is not closed again when dropped
+        let closed = host.calls_to("ctx", "CloseStream");
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[1].params()["stream_id"], "sid2");
+    }
+
+    #[test]
+    fn test_stream_without_id() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond("ctx", "NewStream", json!({ "stream_id": "" }))
+            .respond(
+                "ctx",
+                "NewFileStream",
+                json!({ "stream_id": "", "file_name": "" }),
+            );
+        let _guard = host.install();
+        let qwt: QWriteToken = QWT.parse().unwrap();
+        assert!(matches!(bcc.new_stream(), Err(ErrorKinds::IO(_))));
+        assert!(matches!(bcc.new_file_stream(), Err(ErrorKinds::IO(_))));
+        assert!(matches!(
+            bcc.create_file_writer(&qwt, "assets/owls.txt", "text/plain"),
+            Err(ErrorKinds::IO(_))
+        ));
+        assert!(matches!(
+            bcc.create_part_writer(&qwt),
+            Err(ErrorKinds::IO(_))
+        ));
+        host.assert_not_called("ctx", "CloseStream");
+    }
+
+    #[test]
+    fn test_batched_writer() {
+        let bcc = BitcodeContext::default();
+        let mut host = ScriptedHost::new();
+        for n in [15, 15, 15, 5, 32, 12] {
+            host = host.respond_raw("fos", "Write", &written(n));
+        }
+        let _guard = host.install();
+        let mut fw = FabricStreamWriter::new(&bcc, "fos".to_string(), 0).batched(16);
+        for _ in 0..10 {
+            fw.write_all(b"tern ").unwrap();
+        }
+        // 3 full batches of 15 bytes, the rest waits in the buffer
+        assert_eq!(fw.stats().host_calls, 3);
+        assert_eq!(fw.buffered(), 5);
+        fw.write_all(b"a long write skipping the buffer").unwrap();
+        assert_eq!(fw.stats().host_calls, 5);
+        let header = [b'h'; 4];
+        let padding = [b'.'; 4];
+        let slices = [
+            IoSlice::new(&header),
+            IoSlice::new(b"gull"),
+            IoSlice::new(&padding),
+        ];
+        assert_eq!(fw.write_vectored(&slices).unwrap(), 12);
+        assert_eq!(fw.stats().host_calls, 5);
+        let fw = fw.into_inner().unwrap();
+        assert_eq!(fw.size, 94);
+
+        let writes = host.calls_to("fos", "Write");
+        assert_eq!(writes.len(), 6);
+        assert_eq!(writes[4].payload, b"a long write skipping the buffer");
+        assert_eq!(writes[5].payload, b"hhhhgull....");
+        let out: Vec<u8> = writes.into_iter().flat_map(|c| c.payload).collect();
+        assert_eq!(out.len(), 94);
+        assert!(out.starts_with(b"tern tern tern tern "));
+    }
+
+    #[test]
+    fn test_file_writer() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond(
+                "ctx",
+                "NewFileStream",
+                json!({ "stream_id": "sid1", "file_name": "file1" }),
+            )
+            .respond_raw("*", "Write", &written(5))
+            .respond_raw("*", "Write", &written(6))
+            .respond(
+                "core",
+                "QCreateFileFromStream",
+                // the content of a write token is sent without its hash, type or library
+                json!({
+                    "content": { "id": "iq__4MyXwriZzDG25deHUFUNCDkmi6Az", "write_token": QWT },
+                    "part": { "write_token": QWT, "hash": QPHASH, "size": 11 },
+                }),
+            )
+            .respond("ctx", "CloseStream", json!(""));
+        let _guard = host.install();
+        let qwt: QWriteToken = QWT.parse().unwrap();
+        let mut fw = bcc
+            .create_file_writer(&qwt, "assets/owls.txt", "text/plain")
+            .unwrap();
+        fw.write_all(b"barn\n").unwrap();
+        fw.write_all(b"snowy\n").unwrap();
+        assert_eq!(fw.size(), 11);
+        let info = fw.finish().unwrap();
+        assert_eq!(info.part.size, 11);
+        assert_eq!(info.part.hash, QPHASH);
+        assert_eq!(info.content.write_token, QWT);
+        assert!(info.content.hash.is_empty());
+
+        let create = host.calls_to("core", "QCreateFileFromStream")[0].params();
+        assert_eq!(
+            create,
+            json!({
+                "stream_id": "sid1",
+                "qwtoken": QWT,
+                "path": "assets/owls.txt",
+                "mime": "text/plain",
+                "size": 11,
+            })
+        );
+        host.assert_calls(&[
+            ("ctx", "NewFileStream"),
+            ("sid1", "Write"),
+            ("sid1", "Write"),
+            ("core", "QCreateFileFromStream"),
+            ("ctx", "CloseStream"),
+        ]);
+    }
+
+    #[test]
+    fn test_part_writer() {
+        use sha2::Digest;
+
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond("ctx", "NewStream", json!({ "stream_id": "sid1" }))
+            .respond_raw("*", "Write", &written(4))
+            .respond_raw("*", "Write", &written(4))
+            .respond_raw("*", "Write", &written(6))
+            .respond(
+                "core",
+                "QCreatePartFromStream",
+                json!({ "qphash": QPHASH, "size": 14 }),
+            )
+            .respond("ctx", "CloseStream", json!(""));
+        let _guard = host.install();
+        let qwt: QWriteToken = QWT.parse().unwrap();
+        let mut pw = bcc
+            .create_part_writer(&qwt)
+            .unwrap()
+            .with_checksum("sha256")
+            .unwrap();
+        for chunk in ["hawk", "kite", "osprey"] {
+            pw.write_all(chunk.as_bytes()).unwrap();
+        }
+        assert_eq!(pw.size(), 14);
+        let sum = pw.checksum().unwrap();
+        let part = pw.finish().unwrap();
+        assert_eq!(part.size, 14);
+        assert_eq!(part.qphash, QPHASH);
+        let expected: String = sha2::Sha256::digest(b"hawkkiteosprey")
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        assert_eq!(sum, expected);
+        assert_eq!(
+            host.calls_to("core", "QCreatePartFromStream")[0].params(),
+            json!({ "qwtoken": QWT, "stream_id": "sid1" })
+        );
+        assert_eq!(host.calls_to("ctx", "CloseStream").len(), 1);
+
+        assert!(bcc
+            .create_part_writer(&qwt)
+            .unwrap()
+            .with_checksum("crc")
+            .is_err());
+    }
+
+    #[test]
+    fn test_batched_writer_errors() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new()
+            .respond_raw("*", "Write", br#"{"written" : 3}"#)
+            .fail("*", "Write", ErrorKinds::IO("stream closed".to_string()));
+        let _guard = host.install();
+        let mut bw = FabricStreamWriter::new(&bcc, "sid1".to_string(), 0).batched(8);
+        bw.write_all(b"kestrel").unwrap();
+        // the fabric takes 3 bytes then fails, the rest stays buffered
+        assert!(bw.flush().is_err());
+        assert_eq!(bw.buffered(), 4);
+        assert!(bw.flush().is_err());
+        assert_eq!(bw.buffered(), 4);
+
+        // the first slice fits the batch, the second needs a flush which fails
+        let slices = [IoSlice::new(b"owl"), IoSlice::new(b"harrier")];
+        assert_eq!(bw.write_vectored(&slices).unwrap(), 3);
+        assert_eq!(bw.buffered(), 7);
+        assert!(bw.write_vectored(&slices[1..]).is_err());
+        assert_eq!(bw.stats().bytes, 3);
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        let bcc = BitcodeContext::default();
+        let host = ScriptedHost::new().fail(
+            "*",
+            "Reader",
+            ErrorKinds::Permission("stream closed".to_string()),
+        );
+        let _guard = host.install();
+        let mut reader = FabricStreamReader::new("sid1".to_string(), &bcc);
+        let mut buf = [0u8; 8];
+        let e = reader.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+        let fe = e.get_ref().and_then(|e| e.downcast_ref::<FabricError>());
+        assert!(matches!(fe.unwrap().kind, ErrorKinds::Permission(_)));
+        host.assert_calls(&[("sid1", "Reader")]);
+        assert_eq!(reader.stats().host_calls, 1);
+
+        let _guard = install_host(|_: &str, _: &str, _: &str, _: &[u8]| -> CallResult {
+            Err(r#"{"error" : {"op" : "write", "kind" : "item does not exist"}}"#.into())
+        });
+        let mut writer = FabricStreamWriter::new(&bcc, "sid2".to_string(), 0);
+        let e = writer.write(b"data").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert_eq!(writer.size, 0);
     }
 }
@@ -0,0 +1,374 @@
+//! Ids is a logical separation of the strongly typed fabric identifiers <br>
+//! Fabric identifiers are a type prefix followed by a base58 payload e.g.
+//! * `ilib...` - [QLibId] a content library id
+//! * `iq__...` - [QId] a content object id
+//! * `hq__...` - [QHash] a content object version hash
+//! * `tqw__...` - [QWriteToken] a content object write token
+//! * `hqp_...`, `hqpe...` - [QPartHash] a part hash (unencrypted, encrypted)
+//!
+//! Each type validates its prefix and payload when parsed.  [QStateStoreId] has no prefix and any non-empty
+//! id is accepted.
+//! ```rust
+//! use elvwasm::{QHash, QWriteToken};
+//! let qhash: QHash = "hq__CDkDyULmCVq6RwbwSBG2DVCReuGmQy14PsjtxYMNVDu9i7qHgnstvyzQiGhki8S8sRJR4wGGUz".parse().unwrap();
+//! assert!("tqw__0OIl".parse::<QWriteToken>().is_err());
+//! ```
+//! See [crate::fabric_id] to decode the contents of an id.
+//! An empty string is accepted when deserializing, as the fabric omits ids that do not apply, and yields the
+//! empty default value of the type.
+
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate thiserror;
+extern crate wapc_guest as guest;
+
+use crate::fabric_id::is_base58;
+use crate::ErrorKinds;
+
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+fn validate_id(kind: &str, prefixes: &[&str], s: &str) -> Result<(), ErrorKinds> {
+    // ids without a prefix have no known format
+    if prefixes.is_empty() {
+        if s.is_empty() {
+            return Err(ErrorKinds::Invalid(format!("invalid {kind}: empty id")));
+        }
+        return Ok(());
+    }
+    match prefixes.iter().find_map(|p| s.strip_prefix(p)) {
+        Some(p) if is_base58(p) => Ok(()),
+        Some(_) => Err(ErrorKinds::Invalid(format!(
+            "invalid {kind} {s}: payload is not base58"
+        ))),
+        None => Err(ErrorKinds::Invalid(format!(
+            "invalid {kind} {s}: expected prefix {}",
+            prefixes.join(" or ")
+        ))),
+    }
+}
+
+macro_rules! fabric_id {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident => [$($prefix:literal),*];
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+            #[serde(try_from = "String", into = "String")]
+            pub struct $name(String);
+
+            impl $name {
+                /// the accepted prefixes of the id
+                pub const PREFIXES: &'static [&'static str] = &[$($prefix),*];
+
+                /// new validates s returning the typed id
+                pub fn new(s: &str) -> Result<$name, ErrorKinds> {
+                    validate_id(stringify!($name), Self::PREFIXES, s)?;
+                    Ok($name(s.to_string()))
+                }
+
+                pub fn as_str(&self) -> &str {
+                    &self.0
+                }
+            }
+
+            impl FromStr for $name {
+                type Err = ErrorKinds;
+                fn from_str(s: &str) -> Result<$name, ErrorKinds> {
+                    $name::new(s)
+                }
+            }
+
+            impl TryFrom<String> for $name {
+                type Error = ErrorKinds;
+                fn try_from(s: String) -> Result<$name, ErrorKinds> {
+                    if s.is_empty() {
+                        return Ok($name::default());
+                    }
+                    validate_id(stringify!($name), Self::PREFIXES, &s)?;
+                    Ok($name(s))
+                }
+            }
+
+            impl TryFrom<&str> for $name {
+                type Error = ErrorKinds;
+                fn try_from(s: &str) -> Result<$name, ErrorKinds> {
+                    $name::new(s)
+                }
+            }
+
+            impl From<$name> for String {
+                fn from(id: $name) -> String {
+                    id.0
+                }
+            }
+
+            impl Deref for $name {
+                type Target = str;
+                fn deref(&self) -> &str {
+                    &self.0
+                }
+            }
+
+            impl AsRef<str> for $name {
+                fn as_ref(&self) -> &str {
+                    &self.0
+                }
+            }
+
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(&self.0)
+                }
+            }
+
+            impl PartialEq<str> for $name {
+                fn eq(&self, other: &str) -> bool {
+                    self.0 == other
+                }
+            }
+
+            impl PartialEq<&str> for $name {
+                fn eq(&self, other: &&str) -> bool {
+                    self.0 == *other
+                }
+            }
+        )*
+    };
+}
+
+fabric_id! {
+    /// QLibId identifies a content library
+    QLibId => ["ilib"];
+    /// QId identifies a content object independent of version
+    QId => ["iq__"];
+    /// QHash identifies a finalized version of a content object
+    QHash => ["hq__"];
+    /// QWriteToken identifies a draft of a content object open for writing
+    QWriteToken => ["tqw__"];
+    /// QPartHash identifies a content part, `hqpe` parts are encrypted
+    QPartHash => ["hqp_", "hqpe"];
+    /// QStateStoreId identifies a fabric state store as returned by [crate::BitcodeContext::q_create_q_state_store]
+    QStateStoreId => [];
+}
+
+macro_rules! fabric_id_union {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident { $($variant:ident($id:ident)),* };
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+            #[serde(try_from = "String", into = "String")]
+            pub enum $name {
+                $($variant($id)),*
+            }
+
+            impl $name {
+                pub fn as_str(&self) -> &str {
+                    match self {
+                        $($name::$variant(id) => id.as_str()),*
+                    }
+                }
+            }
+
+            impl FromStr for $name {
+                type Err = ErrorKinds;
+                fn from_str(s: &str) -> Result<$name, ErrorKinds> {
+                    $(
+                        if $id::PREFIXES.iter().any(|p| s.starts_with(p)) {
+                            return Ok($name::$variant(s.parse()?));
+                        }
+                    )*
+                    let prefixes: Vec<&str> = [$($id::PREFIXES),*].concat();
+                    Err(ErrorKinds::Invalid(format!(
+                        "invalid {} {s}: expected prefix {}",
+                        stringify!($name),
+                        prefixes.join(" or ")
+                    )))
+                }
+            }
+
+            impl TryFrom<String> for $name {
+                type Error = ErrorKinds;
+                fn try_from(s: String) -> Result<$name, ErrorKinds> {
+                    if s.is_empty() {
+                        return Ok($name::default());
+                    }
+                    s.parse()
+                }
+            }
+
+            impl From<$name> for String {
+                fn from(id: $name) -> String {
+                    id.as_str().to_string()
+                }
+            }
+
+            $(
+                impl From<$id> for $name {
+                    fn from(id: $id) -> $name {
+                        $name::$variant(id)
+                    }
+                }
+            )*
+
+            impl Deref for $name {
+                type Target = str;
+                fn deref(&self) -> &str {
+                    self.as_str()
+                }
+            }
+
+            impl AsRef<str> for $name {
+                fn as_ref(&self) -> &str {
+                    self.as_str()
+                }
+            }
+
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(self.as_str())
+                }
+            }
+        )*
+    };
+}
+
+fabric_id_union! {
+    /// HashOrToken refers to a content version either by a finalized hash or by a write token
+    HashOrToken { Hash(QHash), Token(QWriteToken) };
+    /// IdHashOrToken refers to content by its id, meaning its latest version, or as a [HashOrToken]
+    IdHashOrToken { Id(QId), Hash(QHash), Token(QWriteToken) };
+}
+
+impl HashOrToken {
+    pub fn is_token(&self) -> bool {
+        matches!(self, HashOrToken::Token(_))
+    }
+}
+
+impl Default for HashOrToken {
+    fn default() -> HashOrToken {
+        HashOrToken::Hash(QHash::default())
+    }
+}
+
+impl Default for IdHashOrToken {
+    fn default() -> IdHashOrToken {
+        IdHashOrToken::Hash(QHash::default())
+    }
+}
+
+impl From<HashOrToken> for IdHashOrToken {
+    fn from(hot: HashOrToken) -> IdHashOrToken {
+        match hot {
+            HashOrToken::Hash(h) => IdHashOrToken::Hash(h),
+            HashOrToken::Token(t) => IdHashOrToken::Token(t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bccontext_testing::ScriptedHost;
+    use serde_json::json;
+
+    #[test]
+    fn test_id_validation() {
+        let h = "hq__CDkDyULmCVq6RwbwSBG2DVCReuGmQy14PsjtxYMNVDu9i7qHgnstvyzQiGhki8S8sRJR4wGGUz";
+        assert!(h.parse::<QHash>().is_ok());
+        assert!(h.parse::<QId>().is_err());
+        assert!("iq__4MyXwriZzDG25deHUFUNCDkmi6Az".parse::<QId>().is_ok());
+        assert!("ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ".parse::<QLibId>().is_ok());
+        assert!("hqpeojPFNiByiRr1BAQdfpxGxqwkdpncy7dDD1TVBuGaMrviJdRae"
+            .parse::<QPartHash>()
+            .is_ok());
+        assert!("iq__".parse::<QId>().is_err());
+        assert!("iq__0OIl".parse::<QId>().is_err());
+        assert!("".parse::<QLibId>().is_err());
+        assert!("qss-0OIl_1".parse::<QStateStoreId>().is_ok());
+        assert!("".parse::<QStateStoreId>().is_err());
+
+        let hot: HashOrToken =
+            "tqw__HSZanpatHeCTnKisyqwKUS8BEN4hErCxtxJe9VyqAy8q75bHcDBUSoMBbmRPvyr4yDJp4PCUMvFw1BBoLV2"
+                .parse()
+                .unwrap();
+        assert!(hot.is_token());
+        assert!(h.parse::<HashOrToken>().map(|h| !h.is_token()).unwrap());
+        assert!("iq__4MyXwriZzDG25deHUFUNCDkmi6Az"
+            .parse::<HashOrToken>()
+            .is_err());
+        let ihot: IdHashOrToken = "iq__4MyXwriZzDG25deHUFUNCDkmi6Az".parse().unwrap();
+        assert!(matches!(ihot, IdHashOrToken::Id(_)));
+        assert_eq!(IdHashOrToken::from(hot.clone()).as_str(), hot.as_str());
+        assert!("ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ"
+            .parse::<IdHashOrToken>()
+            .is_err());
+    }
+
+    // objtar lists the parts of the object_id_or_hash query parameter, which may be an object id
+    #[test]
+    fn test_part_list_by_id() {
+        let host = ScriptedHost::new().respond(
+            "core",
+            "QPartList",
+            json!({ "part_list": { "parts": [] } }),
+        );
+        let _guard = host.install();
+        let bcc = crate::BitcodeContext::default();
+        let obj_id: IdHashOrToken = "iq__4MyXwriZzDG25deHUFUNCDkmi6Az".parse().unwrap();
+        let pl = bcc.q_part_list_typed(&obj_id).unwrap();
+        assert!(pl.part_list.parts.is_empty());
+        assert_eq!(
+            host.calls_to("core", "QPartList")[0].params()["object_id_or_hash"],
+            "iq__4MyXwriZzDG25deHUFUNCDkmi6Az"
+        );
+    }
+
+    #[test]
+    fn test_id_serde() {
+        let id: QId = serde_json::from_value(json!("iq__4MyXwriZzDG25deHUFUNCDkmi6Az")).unwrap();
+        assert_eq!(json!(id), json!("iq__4MyXwriZzDG25deHUFUNCDkmi6Az"));
+        let empty: QWriteToken = serde_json::from_value(json!("")).unwrap();
+        assert!(empty.is_empty());
+        assert!(serde_json::from_value::<QHash>(json!("tqw__abc")).is_err());
+    }
+
+    #[test]
+    fn test_qinfo_ids() {
+        let h = "hq__CDkDyULmCVq6RwbwSBG2DVCReuGmQy14PsjtxYMNVDu9i7qHgnstvyzQiGhki8S8sRJR4wGGUz";
+        let t = "tqw__HSZanpatHeCTnKisyqwKUS8BEN4hErCxtxJe9VyqAy8q75bHcDBUSoMBbmRPvyr4yDJp4PCUMvFw1BBoLV2";
+        let lib = "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ";
+        let qinfo: crate::QInfo =
+            serde_json::from_value(json!({ "hash": h, "qlib_id": lib, "type": "" })).unwrap();
+        assert_eq!(qinfo.qhot().unwrap(), HashOrToken::Hash(h.parse().unwrap()));
+        assert!(qinfo.qwtoken().is_err());
+        assert!(qinfo.qid().is_err());
+
+        let qinfo: crate::QInfo = serde_json::from_value(
+            json!({ "hash": h, "write_token": t, "qlib_id": lib, "type": "" }),
+        )
+        .unwrap();
+        assert!(qinfo.qhot().unwrap().is_token());
+
+        let qinfo: crate::QInfo =
+            serde_json::from_value(json!({ "hash": "", "qlib_id": lib, "type": "" })).unwrap();
+        assert!(qinfo.qhot().is_err());
+        // ids are validated when accessed rather than with the request
+        let qinfo: crate::QInfo =
+            serde_json::from_value(json!({ "hash": t, "qlib_id": "idlib1234", "type": "" }))
+                .unwrap();
+        assert!(qinfo.qhot().is_err());
+        assert!(qinfo.qlibid().is_err());
+        let qinfo: crate::QInfo = serde_json::from_value(json!({ "type": "" })).unwrap();
+        assert!(qinfo.qlibid().is_err());
+    }
+}
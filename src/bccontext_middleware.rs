@@ -0,0 +1,177 @@
+//! Middleware is a logical separation of the hooks run by [crate::jpc] around every registered handler <br>
+//! A [Middleware] may inspect or modify the [BitcodeContext] before the handler runs, short-circuit the
+//! handler entirely by returning a response, and inspect or replace the handler's result afterwards.
+//! Hooks behave as an onion; `before` hooks run in registration order and `after` hooks run in reverse
+//! order for every middleware whose `before` hook was entered.
+
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate thiserror;
+extern crate wapc_guest as guest;
+
+use crate::BitcodeContext;
+
+use guest::CallResult;
+use std::sync::Arc;
+
+pub trait Middleware: Send + Sync {
+    /// before runs prior to the handler.  Returning Some(result) skips the handler and any remaining
+    /// `before` hooks, using result as the response.
+    fn before(&self, _bcc: &mut BitcodeContext) -> Option<CallResult> {
+        None
+    }
+
+    /// after runs with the result of the handler (or of the short-circuiting `before`) and returns
+    /// the result to hand to the next middleware
+    fn after(&self, _bcc: &mut BitcodeContext, result: CallResult) -> CallResult {
+        result
+    }
+}
+
+/// MiddlewareChain is an ordered collection of [Middleware]
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> MiddlewareChain {
+        MiddlewareChain::default()
+    }
+
+    pub fn push<M: Middleware + 'static>(&mut self, m: M) {
+        self.layers.push(Arc::new(m));
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// run invokes handler wrapped by every middleware in the chain
+    pub fn run<F>(&self, bcc: &mut BitcodeContext, handler: F) -> CallResult
+    where
+        F: FnOnce(&mut BitcodeContext) -> CallResult,
+    {
+        let mut entered = 0;
+        let mut short_circuit = None;
+        for m in &self.layers {
+            entered += 1;
+            if let Some(r) = m.before(bcc) {
+                short_circuit = Some(r);
+                break;
+            }
+        }
+        let mut result = match short_circuit {
+            Some(r) => r,
+            None => handler(bcc),
+        };
+        for m in self.layers[..entered].iter().rev() {
+            result = m.after(bcc, result);
+        }
+        result
+    }
+}
+
+/// BeforeFn adapts a plain function into a [Middleware] `before` hook, useful for auth checks
+/// ```ignore
+/// fn require_auth(bcc: &mut elvwasm::BitcodeContext) -> Option<wapc_guest::CallResult> {
+///     if bcc.request.params.http.headers.contains_key("Authorization") {
+///         return None;
+///     }
+///     Some(bcc.make_error_with_kind(elvwasm::ErrorKinds::Permission("no authorization".to_string())))
+/// }
+/// elvwasm::register_middleware(BeforeFn(require_auth));
+/// ```
+pub struct BeforeFn(pub fn(&mut BitcodeContext) -> Option<CallResult>);
+
+impl Middleware for BeforeFn {
+    fn before(&self, bcc: &mut BitcodeContext) -> Option<CallResult> {
+        (self.0)(bcc)
+    }
+}
+
+/// AfterFn adapts a plain function into a [Middleware] `after` hook, useful for error translation
+pub struct AfterFn(pub fn(&mut BitcodeContext, CallResult) -> CallResult);
+
+impl Middleware for AfterFn {
+    fn after(&self, bcc: &mut BitcodeContext, result: CallResult) -> CallResult {
+        (self.0)(bcc, result)
+    }
+}
+
+/// RequestLogger logs each request on entry and its outcome on exit
+pub struct RequestLogger;
+
+impl Middleware for RequestLogger {
+    fn before(&self, bcc: &mut BitcodeContext) -> Option<CallResult> {
+        let http = &bcc.request.params.http;
+        let _ = bcc.log_info(&format!(
+            "request id={} method={} verb={} path={}",
+            bcc.request.id, bcc.request.method, http.verb, http.path
+        ));
+        None
+    }
+
+    fn after(&self, bcc: &mut BitcodeContext, result: CallResult) -> CallResult {
+        let _ = match &result {
+            Ok(r) => bcc.log_info(&format!(
+                "request id={} completed len={}",
+                bcc.request.id,
+                r.len()
+            )),
+            Err(e) => bcc.log_error(&format!("request id={} failed error={e}", bcc.request.id)),
+        };
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKinds;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static AFTER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    fn handler_for_test(bcc: &mut BitcodeContext) -> CallResult {
+        bcc.make_success("DONE")
+    }
+
+    fn block_path(bcc: &mut BitcodeContext) -> Option<CallResult> {
+        if bcc.request.params.http.path == "/blocked" {
+            return Some(bcc.make_error_with_kind(ErrorKinds::Permission("blocked".to_string())));
+        }
+        None
+    }
+
+    fn count_after(_bcc: &mut BitcodeContext, result: CallResult) -> CallResult {
+        AFTER_COUNT.fetch_add(1, Ordering::SeqCst);
+        result
+    }
+
+    #[test]
+    fn test_middleware_short_circuit() {
+        let mut chain = MiddlewareChain::new();
+        chain.push(AfterFn(count_after));
+        chain.push(BeforeFn(block_path));
+        chain.push(AfterFn(count_after));
+
+        let mut bcc = BitcodeContext::default();
+        bcc.request.params.http.path = "/blocked".to_string();
+        let res: serde_json::Value =
+            serde_json::from_slice(&chain.run(&mut bcc, handler_for_test).unwrap()).unwrap();
+        assert!(res.get("error").is_some());
+        assert_eq!(AFTER_COUNT.load(Ordering::SeqCst), 1);
+
+        bcc.request.params.http.path = "/open".to_string();
+        let res: serde_json::Value =
+            serde_json::from_slice(&chain.run(&mut bcc, handler_for_test).unwrap()).unwrap();
+        assert_eq!(res["result"], "DONE");
+        assert_eq!(AFTER_COUNT.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,197 @@
+//! Router is a logical separation of the handler dispatch performed by [crate::jpc] <br>
+//! Handlers may be registered against a verb and a path pattern rather than the exact jpc method name.
+//! Patterns are made up of `/` separated segments where
+//! * `literal` - must match the path segment exactly
+//! * `{name}` - matches any single segment and captures it as `name`
+//! * `*name` - matches the remainder of the path (possibly empty) and captures it as `name`
+//!
+//! e.g. `/image/{offering}/assets/*path` matches `/image/default/assets/birds/robin.jpg` capturing
+//! `offering=default` and `path=birds/robin.jpg`.  The captured values are available to the handler
+//! via [BitcodeContext::path_param].
+
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate thiserror;
+extern crate wapc_guest as guest;
+
+use crate::{ErrorKinds, HandlerFunction};
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+/// A single verb and path pattern associated with a handler
+#[derive(Clone)]
+pub struct Route {
+    verb: String,
+    segments: Vec<Segment>,
+    handler: HandlerFunction,
+}
+
+/// The result of a successful route match
+#[derive(Clone)]
+pub struct RouteMatch {
+    pub handler: HandlerFunction,
+    pub params: HashMap<String, String>,
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+impl Route {
+    /// new parses a pattern into a route
+    /// # Arguments
+    /// * `verb` - http verb to match (case insensitive), "" or "*" matches any verb
+    /// * `pattern` - path pattern as described in [crate::bccontext_router]
+    /// * `handler` - the handler to invoke on match
+    pub fn new(verb: &str, pattern: &str, handler: HandlerFunction) -> Result<Route, ErrorKinds> {
+        let mut segments = Vec::new();
+        let parts: Vec<&str> = split_path(pattern).collect();
+        for (i, part) in parts.iter().enumerate() {
+            let seg = if let Some(name) = part.strip_prefix('*') {
+                if i != parts.len() - 1 {
+                    return Err(ErrorKinds::Invalid(format!(
+                        "wildcard must be the last segment in pattern {pattern}"
+                    )));
+                }
+                Segment::Wildcard(name.to_string())
+            } else if part.starts_with('{') && part.ends_with('}') && part.len() > 2 {
+                Segment::Param(part[1..part.len() - 1].to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            };
+            segments.push(seg);
+        }
+        Ok(Route {
+            verb: verb.to_uppercase(),
+            segments,
+            handler,
+        })
+    }
+
+    fn verb_matches(&self, verb: &str) -> bool {
+        self.verb.is_empty() || self.verb == "*" || self.verb.eq_ignore_ascii_case(verb)
+    }
+
+    /// matches attempts to match the verb and path returning the captured segments on success
+    pub fn matches(&self, verb: &str, path: &str) -> Option<HashMap<String, String>> {
+        if !self.verb_matches(verb) {
+            return None;
+        }
+        let parts: Vec<&str> = split_path(path).collect();
+        let mut params = HashMap::new();
+        let mut idx = 0;
+        for seg in &self.segments {
+            match seg {
+                Segment::Literal(l) => {
+                    if parts.get(idx) != Some(&l.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), parts.get(idx)?.to_string());
+                }
+                Segment::Wildcard(name) => {
+                    params.insert(name.clone(), parts[idx..].join("/"));
+                    return Some(params);
+                }
+            }
+            idx += 1;
+        }
+        if idx != parts.len() {
+            return None;
+        }
+        Some(params)
+    }
+}
+
+/// Router holds the registered routes in registration order and an optional default handler
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    default: Option<HandlerFunction>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// add_route appends a route, earlier routes take precedence
+    pub fn add_route(
+        &mut self,
+        verb: &str,
+        pattern: &str,
+        handler: HandlerFunction,
+    ) -> Result<(), ErrorKinds> {
+        self.routes.push(Route::new(verb, pattern, handler)?);
+        Ok(())
+    }
+
+    /// set_default sets the handler to be called when neither a route nor a method matches
+    pub fn set_default(&mut self, handler: HandlerFunction) {
+        self.default = Some(handler);
+    }
+
+    pub fn default_handler(&self) -> Option<HandlerFunction> {
+        self.default
+    }
+
+    /// route finds the first route matching verb and path
+    pub fn route(&self, verb: &str, path: &str) -> Option<RouteMatch> {
+        self.routes.iter().find_map(|r| {
+            r.matches(verb, path).map(|params| RouteMatch {
+                handler: r.handler,
+                params,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BitcodeContext;
+    use guest::CallResult;
+
+    fn handler_for_test(bcc: &mut BitcodeContext) -> CallResult {
+        bcc.make_success("DONE")
+    }
+
+    #[test]
+    fn test_route_params() {
+        let r = Route::new("GET", "/image/{offering}/assets/*path", handler_for_test).unwrap();
+        let params = r
+            .matches("get", "/image/default/assets/birds/robin.jpg")
+            .unwrap();
+        assert_eq!(params["offering"], "default");
+        assert_eq!(params["path"], "birds/robin.jpg");
+        assert!(r.matches("POST", "/image/default/assets/a.jpg").is_none());
+        assert!(r.matches("GET", "/image/default/files/a.jpg").is_none());
+        let params = r.matches("GET", "/image/default/assets").unwrap();
+        assert_eq!(params["path"], "");
+    }
+
+    #[test]
+    fn test_router_order() {
+        let mut router = Router::new();
+        router
+            .add_route("*", "/meta/{key}", handler_for_test)
+            .unwrap();
+        router.add_route("", "/meta", handler_for_test).unwrap();
+        assert!(router.route("PUT", "/meta/").is_some());
+        assert_eq!(
+            router.route("GET", "/meta/foo").unwrap().params["key"],
+            "foo"
+        );
+        assert!(router.route("GET", "/meta/foo/bar").is_none());
+        assert!(Route::new("GET", "/a/*rest/b", handler_for_test).is_err());
+    }
+}
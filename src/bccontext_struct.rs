@@ -4,28 +4,44 @@ extern crate serde_json;
 extern crate thiserror;
 extern crate wapc_guest as guest;
 
+use crate::fabric_id::{ObjectHash, PartHash};
+use crate::{ErrorKinds, HashOrToken, QHash, QId, QLibId, QPartHash, QWriteToken};
+
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::str;
+use std::str::FromStr;
 use wapc_guest::CallResult;
 
 /// Q is a bitcode representation of an individual piece of content from the fabric
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Q {
-    pub id: String,
-    pub hash: String,
+    pub id: QId,
+    pub hash: QHash,
     #[serde(default)]
-    pub write_token: String,
+    pub write_token: QWriteToken,
     #[serde(rename = "type")]
     pub q_type: String,
-    pub qlib_id: String,
+    pub qlib_id: QLibId,
     #[serde(default)]
     pub meta: serde_json::Value,
     #[serde(default)]
     pub size_stats: SizeStats,
 }
 
+impl Q {
+    /// decoded_hash returns the digest, size and object id contained in the content's hash
+    pub fn decoded_hash(&self) -> Result<ObjectHash, ErrorKinds> {
+        self.hash.decode()
+    }
+
+    /// address returns the `0x` prefixed contract address of the content
+    pub fn address(&self) -> Result<String, ErrorKinds> {
+        self.id.address()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct SizeStats {
     pub parts: i32,
@@ -51,7 +67,7 @@ pub struct QError {
 /// QRef is a bitcode representation of versioned content from the fabric
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct QRef {
-    pub id: String,
+    pub id: QId,
     pub versions: Vec<Q>,
 }
 
@@ -60,58 +76,23 @@ pub struct WritePartResult {
     pub written: usize,
 }
 
-impl TryFrom<CallResult> for WritePartResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<WritePartResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct CreateResult {
-    pub qid: String,
-    pub qwtoken: String,
-}
-
-impl TryFrom<CallResult> for CreateResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<CreateResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
+    pub qid: QId,
+    pub qwtoken: QWriteToken,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct CreatePartResult {
-    pub qphash: String,
+    pub qphash: QPartHash,
     pub size: i64,
 }
 
-impl TryFrom<CallResult> for CreatePartResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<CreatePartResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct SystemTimeResult {
     pub time: u64,
 }
 
-impl TryFrom<CallResult> for SystemTimeResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<SystemTimeResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ExternalCallResult {
     pub function_return: serde_json::Value,
@@ -119,37 +100,10 @@ pub struct ExternalCallResult {
     pub format: Vec<String>,
 }
 
-impl TryFrom<CallResult> for ExternalCallResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<ExternalCallResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
-impl TryFrom<Vec<u8>> for ExternalCallResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: Vec<u8>,
-    ) -> Result<ExternalCallResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct FinalizeCallResult {
-    pub qid: String,
-    pub qhash: String,
-}
-
-impl TryFrom<CallResult> for FinalizeCallResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<FinalizeCallResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
+    pub qid: QId,
+    pub qhash: QHash,
 }
 
 /// Bitcode representation of a full content listing given an optional filter
@@ -162,15 +116,6 @@ pub struct QList {
     pub errors: Vec<QError>,
 }
 
-impl TryFrom<CallResult> for QList {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<QList, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 /// Bitcode representation of a fabric FileStream
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FileStream {
@@ -180,29 +125,12 @@ pub struct FileStream {
     pub file_name: String,
 }
 
-impl TryFrom<CallResult> for FileStream {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<FileStream, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
 /// Bitcode representation of the size of a fabric stream
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FileStreamSize {
     pub file_size: usize,
 }
 
-impl TryFrom<CallResult> for FileStreamSize {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<FileStreamSize, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 /// Bitcode representation of the JPC (JSON Procedure Call) parameters from a client request
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct JpcParams {
@@ -236,12 +164,15 @@ pub struct HttpParams {
 }
 
 /// Bitcode representation of a content sans meta data
+/// The ids are kept as received so that any request may be dispatched, they are validated when converted
+/// to their typed form e.g. [QInfo::qhash], which reports an id the fabric left out as an error
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct QInfo {
     #[serde(default)]
     pub hash: String,
     #[serde(default)]
     pub id: String,
+    #[serde(default)]
     pub qlib_id: String,
     #[serde(rename = "type")]
     pub qtype: String,
@@ -250,32 +181,61 @@ pub struct QInfo {
 }
 
 impl QInfo {
-    pub fn qhot(&self) -> String {
-        let s: String = if !self.write_token.is_empty() {
-            self.write_token.to_string()
+    /// qhot returns the write token of the context's content if it is being written, otherwise its hash
+    pub fn qhot(&self) -> Result<HashOrToken, ErrorKinds> {
+        if !self.write_token.is_empty() {
+            Ok(HashOrToken::Token(self.qwtoken()?))
         } else {
-            self.hash.to_string()
-        };
-        s
+            Ok(HashOrToken::Hash(self.qhash()?))
+        }
+    }
+
+    /// qhash validates the hash of the context's content
+    pub fn qhash(&self) -> Result<QHash, ErrorKinds> {
+        required(&self.hash, "hash")
+    }
+
+    /// qid validates the id of the context's content
+    pub fn qid(&self) -> Result<QId, ErrorKinds> {
+        required(&self.id, "id")
+    }
+
+    /// qlibid validates the library id of the context's content
+    pub fn qlibid(&self) -> Result<QLibId, ErrorKinds> {
+        required(&self.qlib_id, "qlib_id")
+    }
+
+    /// qwtoken validates the write token of the context's content
+    pub fn qwtoken(&self) -> Result<QWriteToken, ErrorKinds> {
+        required(&self.write_token, "write_token")
     }
 }
 
+fn required<T: FromStr<Err = ErrorKinds>>(id: &str, name: &str) -> Result<T, ErrorKinds> {
+    if id.is_empty() {
+        return Err(ErrorKinds::Invalid(format!("the request has no {name}")));
+    }
+    id.parse()
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct QPart {
     #[serde(default)]
-    pub write_token: String,
+    pub write_token: QWriteToken,
     #[serde(default)]
-    pub hash: String,
+    pub hash: QPartHash,
     #[serde(default)]
     pub size: i64,
 }
 
-impl TryFrom<CallResult> for QPart {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<QPart, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
+impl QPart {
+    /// decoded_hash returns the digest and size contained in the part's hash
+    pub fn decoded_hash(&self) -> Result<PartHash, ErrorKinds> {
+        self.hash.decode()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.hash.starts_with("hqpe")
     }
 }
 
@@ -287,32 +247,32 @@ pub struct QPartListContents {
     pub parts: Vec<QPart>,
 }
 
-impl TryFrom<CallResult> for QPartListContents {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<QPartListContents, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct QPartList {
     pub part_list: QPartListContents,
 }
 
-impl TryFrom<CallResult> for QPartList {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<QPartList, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
+/// QPartContent is the content holding a new part as reported by the fabric, which leaves out what does
+/// not apply e.g. the hash of content still being written
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QPartContent {
+    #[serde(default)]
+    pub id: QId,
+    #[serde(default)]
+    pub hash: QHash,
+    #[serde(default)]
+    pub write_token: QWriteToken,
+    #[serde(default, rename = "type")]
+    pub q_type: String,
+    #[serde(default)]
+    pub qlib_id: QLibId,
 }
 
+/// QPartInfo is the result of [crate::BitcodeContext::q_create_file_from_stream]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct QPartInfo {
-    pub content: Q,
+    #[serde(default)]
+    pub content: QPartContent,
     pub part: QPart,
 }
 
@@ -324,30 +284,12 @@ pub struct QFileToStreamResult {
     pub mime_type: String,
 }
 
-impl TryFrom<CallResult> for QFileToStreamResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<QFileToStreamResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct WriteResult {
     #[serde(default)]
     pub written: usize,
 }
 
-impl TryFrom<CallResult> for WriteResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<WriteResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct FetchResult {
     #[serde(default)]
@@ -358,36 +300,10 @@ pub struct FetchResult {
     pub body: String,
 }
 
-impl TryFrom<CallResult> for FetchResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<FetchResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
-impl TryFrom<Vec<u8>> for FetchResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: Vec<u8>,
-    ) -> Result<FetchResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ModifyResult {
     #[serde(default)]
-    pub qwtoken: String,
-}
-impl TryFrom<CallResult> for ModifyResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<ModifyResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
+    pub qwtoken: QWriteToken,
 }
 
 /// Bitcode representation of a incomming client request
@@ -413,10 +329,10 @@ pub struct Response {
     pub method: String,
 }
 
-/// Bitcode representation of a result from new_stream
+/// Bitcode representation of a result from new_stream_raw
 /// ```
 /// fn do_something<'s>(bcc: &'s mut elvwasm::BitcodeContext) -> wapc_guest::CallResult {
-///   let res = bcc.new_stream()?;
+///   let res = bcc.new_stream_raw()?;
 ///   let stream1:elvwasm::NewStreamResult = serde_json::from_slice(&res)?;
 ///   // stream1.stream_id has new id
 ///   Ok(res)
@@ -427,15 +343,6 @@ pub struct NewStreamResult {
     pub stream_id: String,
 }
 
-impl TryFrom<CallResult> for NewStreamResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<NewStreamResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ReadCount {
     pub read: i32,
@@ -446,11 +353,56 @@ pub struct LROResult {
     pub lro_handle: String,
 }
 
-impl TryFrom<CallResult> for LROResult {
-    type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
-    fn try_from(
-        cr: CallResult,
-    ) -> Result<LROResult, Box<dyn std::error::Error + Sync + Send + 'static>> {
-        Ok(serde_json::from_slice(&cr?)?)
-    }
-}
+/// impl_call_result_conversions implements `TryFrom<CallResult>` for result structures so that the raw
+/// results of [crate::BitcodeContext] calls may be converted with `try_into()`.  The optional `bytes`
+/// section additionally implements `TryFrom<Vec<u8>>`.
+macro_rules! impl_call_result_conversions {
+    ($($name:ident),* $(,)? $(; bytes $($bname:ident),* $(,)?)?) => {
+        $(
+            impl TryFrom<CallResult> for $name {
+                type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
+                fn try_from(
+                    cr: CallResult,
+                ) -> Result<$name, Box<dyn std::error::Error + Sync + Send + 'static>> {
+                    Ok(serde_json::from_slice(&cr?)?)
+                }
+            }
+        )*
+        $($(
+            impl TryFrom<Vec<u8>> for $bname {
+                type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
+                fn try_from(
+                    cr: Vec<u8>,
+                ) -> Result<$bname, Box<dyn std::error::Error + Sync + Send + 'static>> {
+                    Ok(serde_json::from_slice(&cr)?)
+                }
+            }
+        )*)?
+    };
+}
+
+impl_call_result_conversions!(
+    WritePartResult,
+    CreateResult,
+    CreatePartResult,
+    SystemTimeResult,
+    ExternalCallResult,
+    FinalizeCallResult,
+    QList,
+    FileStream,
+    FileStreamSize,
+    QPart,
+    QPartListContents,
+    QPartList,
+    QFileToStreamResult,
+    WriteResult,
+    FetchResult,
+    ModifyResult,
+    NewStreamResult,
+    LROResult,
+    QRef,
+    QPartInfo,
+    ReadCount,
+    SeekResult;
+    bytes ExternalCallResult, FetchResult
+);
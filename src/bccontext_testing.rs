@@ -0,0 +1,321 @@
+//! Testing is a logical separation of the native test harness enabled by the `testing` feature <br>
+//! Outside of a wasm host every fabric call made through a [BitcodeContext] ends in a waPC import that does
+//! nothing.  With the `testing` feature the calls are instead routed to a [MockHost] installed on the
+//! current thread, so handlers may be unit tested natively with `cargo test --features testing`
+//! ```ignore
+//! use elvwasm::bccontext_testing::ScriptedHost;
+//! use elvwasm::BitcodeContext;
+//! use serde_json::json;
+//!
+//! let host = ScriptedHost::new().respond("core", "SQMDGet", json!({"title" : "robin"}));
+//! let _guard = host.install();
+//! let bcc = BitcodeContext::default();
+//! let meta: serde_json::Value = serde_json::from_slice(&bcc.sqmd_get_json("/public").unwrap()).unwrap();
+//! assert_eq!(meta["title"], "robin");
+//! host.assert_called("core", "SQMDGet");
+//! assert_eq!(host.calls_to("core", "SQMDGet")[0].params()["path"], "/public");
+//! ```
+//! The host is installed per thread as the test harness runs tests on separate threads.  When no host is
+//! installed calls fall through to the waPC import as before on wasm32, and fail with
+//! [ErrorKinds::NotImplemented] in native builds which have no waPC host to link against.
+
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate thiserror;
+extern crate wapc_guest as guest;
+
+use crate::ErrorKinds;
+
+use guest::CallResult;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// MockHost answers the host calls of a bitcode module in place of the fabric
+pub trait MockHost {
+    /// host_call receives exactly the arguments of the waPC host call
+    /// # Arguments
+    /// * `binding` - the request id of the bitcode call
+    /// * `ns` - the fabric module, or the stream id for stream operations
+    /// * `op` - the fabric method
+    /// * `msg` - the jpc request, or the raw bytes for stream writes
+    fn host_call(&mut self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult;
+}
+
+impl<F> MockHost for F
+where
+    F: FnMut(&str, &str, &str, &[u8]) -> CallResult,
+{
+    fn host_call(&mut self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult {
+        self(binding, ns, op, msg)
+    }
+}
+
+thread_local! {
+    static HOST: RefCell<Option<Box<dyn MockHost>>> = RefCell::new(None);
+}
+
+/// install_host routes the fabric calls of the current thread to host until the returned guard is dropped
+pub fn install_host<H: MockHost + 'static>(host: H) -> HostGuard {
+    let previous = HOST.with(|h| h.borrow_mut().replace(Box::new(host)));
+    HostGuard { previous }
+}
+
+/// HostGuard restores the previously installed host when dropped
+pub struct HostGuard {
+    previous: Option<Box<dyn MockHost>>,
+}
+
+impl Drop for HostGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        HOST.with(|h| *h.borrow_mut() = previous);
+    }
+}
+
+/// host_call is the crate's single path to the fabric, it dispatches to the installed [MockHost] if any
+pub(crate) fn host_call(binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult {
+    // the host is taken out for the duration of the call so that a host may itself use a BitcodeContext
+    match HOST.with(|h| h.borrow_mut().take()) {
+        Some(mut host) => {
+            let res = host.host_call(binding, ns, op, msg);
+            HOST.with(|h| {
+                h.borrow_mut().get_or_insert(host);
+            });
+            res
+        }
+        #[cfg(target_arch = "wasm32")]
+        None => guest::host_call(binding, ns, op, msg),
+        // native builds have no waPC host to import from
+        #[cfg(not(target_arch = "wasm32"))]
+        None => Err(Box::new(ErrorKinds::NotImplemented(format!(
+            "no host installed for {ns} {op}"
+        )))),
+    }
+}
+
+/// A single host call recorded by [ScriptedHost]
+#[derive(Clone, Debug)]
+pub struct RecordedCall {
+    pub binding: String,
+    pub ns: String,
+    pub op: String,
+    pub payload: Vec<u8>,
+}
+
+impl RecordedCall {
+    /// params returns the `params` member of a jpc payload, or the whole payload if it has none.  Payloads
+    /// that are not json, such as stream writes, yield Null
+    pub fn params(&self) -> serde_json::Value {
+        let v: serde_json::Value = serde_json::from_slice(&self.payload).unwrap_or_default();
+        match v.get("params") {
+            Some(p) => p.clone(),
+            None => v,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Reply {
+    Bytes(Vec<u8>),
+    Error(ErrorKinds),
+}
+
+#[derive(Default)]
+struct ScriptState {
+    replies: HashMap<(String, String), VecDeque<Reply>>,
+    calls: Vec<RecordedCall>,
+}
+
+/// ScriptedHost is a [MockHost] answering each (namespace, op) pair with scripted replies and recording
+/// every call made.  Replies scripted for the namespace `*` match any namespace, which is convenient for
+/// stream operations whose namespace is a generated stream id.  Several replies scripted for the same pair
+/// are returned in order, the last one repeating.  Calls with no scripted reply fail with
+/// [ErrorKinds::NotImplemented].
+///
+/// A ScriptedHost is a cheap handle, clones share the same script and recorded calls so the test may keep
+/// one while another is installed.
+#[derive(Clone, Default)]
+pub struct ScriptedHost {
+    state: Rc<RefCell<ScriptState>>,
+}
+
+impl ScriptedHost {
+    pub fn new() -> ScriptedHost {
+        ScriptedHost::default()
+    }
+
+    fn push(self, ns: &str, op: &str, reply: Reply) -> ScriptedHost {
+        self.state
+            .borrow_mut()
+            .replies
+            .entry((ns.to_string(), op.to_string()))
+            .or_default()
+            .push_back(reply);
+        self
+    }
+
+    /// respond scripts a successful jpc reply `{"result" : result}`
+    pub fn respond(self, ns: &str, op: &str, result: serde_json::Value) -> ScriptedHost {
+        let reply = serde_json::json!({ "jpc": "1.0", "result": result });
+        self.push(ns, op, Reply::Bytes(reply.to_string().into_bytes()))
+    }
+
+    /// respond_raw scripts a reply returned unchanged, as used by the stream operations
+    pub fn respond_raw(self, ns: &str, op: &str, reply: &[u8]) -> ScriptedHost {
+        self.push(ns, op, Reply::Bytes(reply.to_vec()))
+    }
+
+    /// respond_error scripts a jpc error reply as the fabric would send it
+    pub fn respond_error(self, ns: &str, op: &str, err: ErrorKinds) -> ScriptedHost {
+        let reply = crate::make_json_error(err, "").unwrap_or_default();
+        self.push(ns, op, Reply::Bytes(reply))
+    }
+
+    /// fail scripts a failure of the host call itself rather than an error reply
+    pub fn fail(self, ns: &str, op: &str, err: ErrorKinds) -> ScriptedHost {
+        self.push(ns, op, Reply::Error(err))
+    }
+
+    /// install routes the fabric calls of the current thread to this host, see [install_host]
+    pub fn install(&self) -> HostGuard {
+        install_host(self.clone())
+    }
+
+    /// calls returns every call made so far in order
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    /// calls_to returns the calls made so far to the given namespace and op, `*` matches any namespace
+    pub fn calls_to(&self, ns: &str, op: &str) -> Vec<RecordedCall> {
+        self.calls()
+            .into_iter()
+            .filter(|c| (ns == "*" || c.ns == ns) && c.op == op)
+            .collect()
+    }
+
+    /// assert_called panics unless at least one call was made to the given namespace and op
+    pub fn assert_called(&self, ns: &str, op: &str) {
+        assert!(
+            !self.calls_to(ns, op).is_empty(),
+            "expected a call to {ns} {op}, calls made: {:?}",
+            self.ops()
+        );
+    }
+
+    /// assert_not_called panics if any call was made to the given namespace and op
+    pub fn assert_not_called(&self, ns: &str, op: &str) {
+        assert!(
+            self.calls_to(ns, op).is_empty(),
+            "unexpected call to {ns} {op}"
+        );
+    }
+
+    /// assert_calls panics unless exactly the given (namespace, op) calls were made in the given order
+    pub fn assert_calls(&self, expected: &[(&str, &str)]) {
+        let ops = self.ops();
+        let matches = ops.len() == expected.len()
+            && ops
+                .iter()
+                .zip(expected)
+                .all(|((ns, op), (ens, eop))| (*ens == "*" || ns == ens) && op == eop);
+        assert!(matches, "expected calls {expected:?}, calls made: {ops:?}");
+    }
+
+    fn ops(&self) -> Vec<(String, String)> {
+        self.state
+            .borrow()
+            .calls
+            .iter()
+            .map(|c| (c.ns.clone(), c.op.clone()))
+            .collect()
+    }
+
+    fn next_reply(&self, ns: &str, op: &str) -> Option<Reply> {
+        let mut state = self.state.borrow_mut();
+        for key in [
+            (ns.to_string(), op.to_string()),
+            ("*".to_string(), op.to_string()),
+        ] {
+            if let Some(q) = state.replies.get_mut(&key) {
+                let reply = if q.len() > 1 {
+                    q.pop_front()
+                } else {
+                    q.front().cloned()
+                };
+                if reply.is_some() {
+                    return reply;
+                }
+            }
+        }
+        None
+    }
+}
+
+impl MockHost for ScriptedHost {
+    fn host_call(&mut self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult {
+        self.state.borrow_mut().calls.push(RecordedCall {
+            binding: binding.to_string(),
+            ns: ns.to_string(),
+            op: op.to_string(),
+            payload: msg.to_vec(),
+        });
+        match self.next_reply(ns, op) {
+            Some(Reply::Bytes(b)) => Ok(b),
+            Some(Reply::Error(e)) => Err(Box::new(e)),
+            None => Err(Box::new(ErrorKinds::NotImplemented(format!(
+                "no scripted reply for {ns} {op}"
+            )))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{jpc, register_handler, BitcodeContext};
+    use serde_json::json;
+
+    fn handler_reading_meta(bcc: &mut BitcodeContext) -> CallResult {
+        let title: serde_json::Value = serde_json::from_slice(&bcc.sqmd_get_json("/title")?)?;
+        bcc.write_stream("fos", title.to_string().as_bytes())?;
+        bcc.make_success_json(&json!({ "title": title }))
+    }
+
+    #[test]
+    fn test_scripted_host() {
+        register_handler("read_meta", handler_reading_meta);
+        let host = ScriptedHost::new()
+            .respond("core", "SQMDGet", json!("robin"))
+            .respond_raw("*", "Write", br#"{"written" : 7}"#);
+        let guard = host.install();
+        let req = json!({
+          "id" : "id1", "jpc" : "1.0", "method" : "read_meta",
+          "params" : { "http" : { "path" : "/read_meta" } },
+          "qinfo" : { "qlib_id" : "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ", "type" : "" },
+        });
+        let res: serde_json::Value =
+            serde_json::from_slice(&jpc(&serde_json::to_vec(&req).unwrap()).unwrap()).unwrap();
+        assert_eq!(res["result"]["title"], "robin");
+        host.assert_calls(&[("core", "SQMDGet"), ("fos", "Write")]);
+        assert_eq!(
+            host.calls_to("core", "SQMDGet")[0].params()["path"],
+            "/title"
+        );
+        assert_eq!(host.calls_to("fos", "Write")[0].payload, b"\"robin\"");
+        drop(guard);
+
+        let host = ScriptedHost::new().respond_error(
+            "core",
+            "SQMDGet",
+            ErrorKinds::NotExist("title".to_string()),
+        );
+        let _guard = host.install();
+        let res: serde_json::Value =
+            serde_json::from_slice(&jpc(&serde_json::to_vec(&req).unwrap()).unwrap()).unwrap();
+        assert_eq!(res["error"]["kind"], "NotExist");
+        host.assert_not_called("fos", "Write");
+    }
+}
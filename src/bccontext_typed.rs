@@ -0,0 +1,275 @@
+//! Typed handlers are a logical separation of the argument decoding and response encoding performed for
+//! handlers of the form
+//! ```ignore
+//! fn do_image(bcc: &mut BitcodeContext, args: ImageArgs) -> Result<ImageInfo, ErrorKinds>
+//! ```
+//! where `ImageArgs: Deserialize` is built from the request's query parameters merged over the fields of a
+//! json object body, and `ImageInfo: Serialize` is wrapped into the jpc success envelope.  Query values are
+//! strings on the wire and are parsed on demand into the type requested by `ImageArgs`, so `height=200`
+//! decodes into a `u32` while `name=200` decodes into a `String`.  Decoding failures are reported to the
+//! client as [ErrorKinds::BadHttpParams].
+//!
+//! Typed handlers are registered through [crate::typed_handler] or the `typed` section of
+//! [crate::implement_bitcode_module]
+
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate thiserror;
+extern crate wapc_guest as guest;
+
+use crate::{BitcodeContext, ErrorKinds, FabricError, HttpParams};
+
+use guest::CallResult;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Wraps a typed handler into a [crate::HandlerFunction] suitable for [crate::register_handler] and friends
+/// ```ignore
+/// register_handler("image", typed_handler!(do_image));
+/// ```
+#[macro_export]
+macro_rules! typed_handler {
+    ($typed_func:path) => {{
+        fn typed_shim(
+            bcc: &mut $crate::BitcodeContext,
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error + Sync + Send>> {
+            $crate::bccontext_typed::call_typed(bcc, $typed_func)
+        }
+        typed_shim as $crate::HandlerFunction
+    }};
+}
+
+/// call_typed decodes the handler arguments from the request, calls the handler and encodes its result
+/// as a jpc success or error response
+pub fn call_typed<A, O, E, F>(bcc: &mut BitcodeContext, handler: F) -> CallResult
+where
+    A: DeserializeOwned,
+    O: Serialize,
+    E: Into<FabricError>,
+    F: FnOnce(&mut BitcodeContext, A) -> Result<O, E>,
+{
+    let args: A = match decode_args(&bcc.request.params.http) {
+        Ok(a) => a,
+        Err(e) => return bcc.make_error_with_kind(e),
+    };
+    match handler(bcc, args) {
+        Ok(out) => bcc.make_success_json(&serde_json::to_value(out)?),
+        Err(e) => bcc.make_error_with_kind(e),
+    }
+}
+
+/// decode_args builds a typed argument value from the query parameters and json body of a request.
+/// Query parameters take precedence over body fields of the same name.
+pub fn decode_args<A: DeserializeOwned>(http: &HttpParams) -> Result<A, ErrorKinds> {
+    let mut fields: BTreeMap<String, ArgValue> = BTreeMap::new();
+    if let Some(body) = http.body.as_object() {
+        for (k, v) in body {
+            fields.insert(k.clone(), ArgValue::Json(v.clone()));
+        }
+    }
+    for (k, v) in &http.query {
+        fields.insert(k.clone(), ArgValue::Query(v.clone()));
+    }
+    let map = MapDeserializer::<_, serde_json::Error>::new(fields.into_iter());
+    A::deserialize(map)
+        .map_err(|e| ErrorKinds::BadHttpParams(format!("unable to decode request arguments: {e}")))
+}
+
+/// A single argument value, either a json value from the body or the string values of a query parameter
+enum ArgValue {
+    Json(serde_json::Value),
+    Query(Vec<String>),
+}
+
+impl ArgValue {
+    fn first(q: &[String]) -> Result<&str, serde_json::Error> {
+        q.first()
+            .map(|s| s.as_str())
+            .ok_or_else(|| de::Error::custom("query parameter has no value"))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for ArgValue {
+    type Deserializer = ArgValue;
+
+    fn into_deserializer(self) -> ArgValue {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident : $ty:ty),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self {
+                    ArgValue::Json(v) => v.$method(visitor),
+                    ArgValue::Query(q) => {
+                        let s = ArgValue::first(&q)?;
+                        let parsed = s.parse::<$ty>().map_err(|e| {
+                            <serde_json::Error as de::Error>::custom(format!("invalid value {s}: {e}"))
+                        })?;
+                        visitor.$visit(parsed)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ArgValue {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            ArgValue::Json(v) => v.deserialize_any(visitor),
+            ArgValue::Query(mut q) => {
+                if q.len() == 1 {
+                    visitor.visit_string(q.remove(0))
+                } else {
+                    SeqDeserializer::<_, serde_json::Error>::new(q.into_iter())
+                        .deserialize_any(visitor)
+                }
+            }
+        }
+    }
+
+    deserialize_parsed!(
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    );
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            ArgValue::Json(v) => v.deserialize_option(visitor),
+            q => visitor.visit_some(q),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            ArgValue::Json(v) => v.deserialize_seq(visitor),
+            ArgValue::Query(q) => visitor.visit_seq(SeqDeserializer::new(
+                q.into_iter().map(|s| ArgValue::Query(vec![s])),
+            )),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            ArgValue::Json(v) => v.deserialize_newtype_struct(name, visitor),
+            q => visitor.visit_newtype_struct(q),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            ArgValue::Json(v) => v.deserialize_enum(name, variants, visitor),
+            ArgValue::Query(q) => {
+                let s = ArgValue::first(&q)?.to_string();
+                visitor.visit_enum(
+                    <String as IntoDeserializer<'de, serde_json::Error>>::into_deserializer(s),
+                )
+            }
+        }
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct ImageArgs {
+        height: u32,
+        name: String,
+        #[serde(default)]
+        crop: Option<bool>,
+        tags: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    struct ImageInfo {
+        height: u32,
+        name: String,
+        tags: usize,
+    }
+
+    fn do_typed(_bcc: &mut BitcodeContext, args: ImageArgs) -> Result<ImageInfo, ErrorKinds> {
+        if args.crop == Some(true) {
+            return Err(ErrorKinds::NotImplemented("crop".to_string()));
+        }
+        Ok(ImageInfo {
+            height: args.height,
+            name: args.name,
+            tags: args.tags.len(),
+        })
+    }
+
+    #[test]
+    fn test_typed_handler() {
+        let mut bcc = BitcodeContext::default();
+        let http = &mut bcc.request.params.http;
+        http.query
+            .insert("height".to_string(), vec!["200".to_string()]);
+        http.query
+            .insert("name".to_string(), vec!["300".to_string()]);
+        http.body = json!({"tags" : ["a", "b"], "height" : 100});
+        let res: serde_json::Value =
+            serde_json::from_slice(&call_typed(&mut bcc, do_typed).unwrap()).unwrap();
+        assert_eq!(
+            res["result"],
+            json!({"height" : 200, "name" : "300", "tags" : 2})
+        );
+
+        bcc.request
+            .params
+            .http
+            .query
+            .insert("height".to_string(), vec!["tall".to_string()]);
+        let res: serde_json::Value =
+            serde_json::from_slice(&call_typed(&mut bcc, do_typed).unwrap()).unwrap();
+        assert!(res["error"]["desc"]["BadHttpParams"].is_string());
+
+        let shim = typed_handler!(do_typed);
+        bcc.request
+            .params
+            .http
+            .query
+            .insert("height".to_string(), vec!["10".to_string()]);
+        bcc.request
+            .params
+            .http
+            .query
+            .insert("crop".to_string(), vec!["true".to_string()]);
+        let res: serde_json::Value = serde_json::from_slice(&shim(&mut bcc).unwrap()).unwrap();
+        assert!(res["error"]["desc"]["NotImplemented"].is_string());
+    }
+}
@@ -0,0 +1,501 @@
+//! fabric_id decodes and encodes the binary layout behind the fabric identifier strings <br>
+//! All identifiers are a type prefix followed by the base58 encoding of
+//! * ids (`ilib`, `iq__`, `inod`) - the 20 byte address of the id's contract
+//! * content hashes (`hq__`) - a 32 byte sha256 digest, the uvarint size of the content and the 20 byte object id
+//! * part hashes (`hqp_`, `hqpe`) - a 32 byte sha256 digest and the uvarint size of the part
+//! * write tokens (`tqw__`) - the uvarint length prefixed object id, node id and random bytes of the token,
+//!   where the ids are in their binary form of a type code byte followed by the 20 byte address
+//!
+//! Content ids and library ids convert to and from their contract addresses with [QId::from_address],
+//! [QId::address] and their [QLibId] equivalents.
+//!
+//! ```rust
+//! use elvwasm::fabric_id::ObjectHash;
+//! let h: ObjectHash = "hq__9G79fi7NKap3TD29EZSFGJbZTpaK8cYXPdUk5TkMkxZGg8E8B5A3QfdmzRcjyZHLBYdDH4RMWn".parse().unwrap();
+//! assert_eq!(h.size, 139);
+//! assert_eq!(h.qid.as_str(), "iq__4MyXwriZzDG25deHUFUNCDkmi6Az");
+//! ```
+
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+extern crate thiserror;
+extern crate wapc_guest as guest;
+
+use crate::{ErrorKinds, QHash, QId, QLibId, QPartHash, QWriteToken};
+
+use std::fmt;
+use std::str::FromStr;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// The length of a sha256 digest
+pub const DIGEST_LEN: usize = 32;
+/// The length of the address of an id
+pub const ID_LEN: usize = 20;
+
+/// The type codes of the binary form of an id, see [WriteToken]
+const CODE_QID: u8 = 4;
+const CODE_NID: u8 = 8;
+
+fn base58_value(c: u8) -> Option<u8> {
+    BASE58_ALPHABET
+        .iter()
+        .position(|&a| a == c)
+        .map(|p| p as u8)
+}
+
+/// is_base58 reports whether s is a non empty base58 (bitcoin alphabet) string
+pub fn is_base58(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|c| base58_value(c).is_some())
+}
+
+/// decode_base58 decodes a base58 (bitcoin alphabet) string
+pub fn decode_base58(s: &str) -> Result<Vec<u8>, ErrorKinds> {
+    let mut out: Vec<u8> = Vec::with_capacity(s.len());
+    for c in s.bytes() {
+        let mut carry = base58_value(c)
+            .ok_or_else(|| ErrorKinds::Invalid(format!("invalid base58 character in {s}")))?
+            as u32;
+        for b in out.iter_mut().rev() {
+            carry += (*b as u32) * 58;
+            *b = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            out.insert(0, (carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|&c| c == b'1').count();
+    let mut res = vec![0; zeros];
+    res.extend(out.into_iter().skip_while(|&b| b == 0));
+    Ok(res)
+}
+
+/// encode_base58 encodes bytes as base58 (bitcoin alphabet)
+pub fn encode_base58(data: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::with_capacity(data.len() * 138 / 100 + 1);
+    for &b in data {
+        let mut carry = b as u32;
+        for d in digits.iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|&&b| b == 0).count();
+    let mut s = String::with_capacity(zeros + digits.len());
+    s.extend(std::iter::repeat('1').take(zeros));
+    s.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|&d| BASE58_ALPHABET[d as usize] as char),
+    );
+    s
+}
+
+fn read_uvarint(data: &[u8]) -> Result<(u64, usize), ErrorKinds> {
+    let mut x: u64 = 0;
+    for (i, &b) in data.iter().enumerate().take(10) {
+        x |= ((b & 0x7f) as u64) << (7 * i);
+        if b < 0x80 {
+            return Ok((x, i + 1));
+        }
+    }
+    Err(ErrorKinds::Invalid("truncated uvarint".to_string()))
+}
+
+fn write_uvarint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn split_prefix<'s>(s: &'s str, prefixes: &[&str]) -> Result<(&'s str, &'s str), ErrorKinds> {
+    prefixes
+        .iter()
+        .find_map(|p| s.strip_prefix(p).map(|rest| (&s[..p.len()], rest)))
+        .ok_or_else(|| {
+            ErrorKinds::Invalid(format!("{s} does not start with {}", prefixes.join(" or ")))
+        })
+}
+
+fn id_bytes(id: &str, prefix: &str) -> Result<Vec<u8>, ErrorKinds> {
+    let (_, payload) = split_prefix(id, &[prefix])?;
+    let b = decode_base58(payload)?;
+    if b.len() != ID_LEN {
+        return Err(ErrorKinds::Invalid(format!(
+            "{id} decodes to {} bytes, expected {ID_LEN}",
+            b.len()
+        )));
+    }
+    Ok(b)
+}
+
+fn address_bytes(address: &str) -> Result<Vec<u8>, ErrorKinds> {
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    if hex.len() != ID_LEN * 2 {
+        return Err(ErrorKinds::Invalid(format!(
+            "address {address} is not {ID_LEN} bytes"
+        )));
+    }
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ErrorKinds::Invalid(format!("address {address} is not hex")));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|e| ErrorKinds::Invalid(format!("address {address} is not hex: {e}")))
+        })
+        .collect()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// id_to_address converts an id of any kind (e.g. `iq__...`, `ilib...`) to its `0x` prefixed contract address
+pub fn id_to_address(id: &str) -> Result<String, ErrorKinds> {
+    let prefix = id.get(..4).unwrap_or_default();
+    Ok(format!("0x{}", to_hex(&id_bytes(id, prefix)?)))
+}
+
+impl QId {
+    /// from_address converts a content contract address to its content id
+    pub fn from_address(address: &str) -> Result<QId, ErrorKinds> {
+        QId::new(&format!("iq__{}", encode_base58(&address_bytes(address)?)))
+    }
+
+    /// address returns the `0x` prefixed contract address of the content
+    pub fn address(&self) -> Result<String, ErrorKinds> {
+        id_to_address(self)
+    }
+}
+
+impl QLibId {
+    /// from_address converts a library contract address to its library id
+    pub fn from_address(address: &str) -> Result<QLibId, ErrorKinds> {
+        QLibId::new(&format!("ilib{}", encode_base58(&address_bytes(address)?)))
+    }
+
+    /// address returns the `0x` prefixed contract address of the library
+    pub fn address(&self) -> Result<String, ErrorKinds> {
+        id_to_address(self)
+    }
+}
+
+/// ObjectHash is the decoded form of a content hash `hq__...`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectHash {
+    pub digest: Vec<u8>,
+    pub size: u64,
+    pub qid: QId,
+}
+
+impl ObjectHash {
+    /// digest_hex returns the hex encoded sha256 digest
+    pub fn digest_hex(&self) -> String {
+        to_hex(&self.digest)
+    }
+
+    /// to_qhash encodes the digest, size and object id as a content hash
+    pub fn to_qhash(&self) -> Result<QHash, ErrorKinds> {
+        let mut b = self.digest.clone();
+        write_uvarint(&mut b, self.size);
+        b.extend(id_bytes(&self.qid, "iq__")?);
+        QHash::new(&format!("hq__{}", encode_base58(&b)))
+    }
+}
+
+impl FromStr for ObjectHash {
+    type Err = ErrorKinds;
+    fn from_str(s: &str) -> Result<ObjectHash, ErrorKinds> {
+        let (_, payload) = split_prefix(s, &["hq__"])?;
+        let b = decode_base58(payload)?;
+        if b.len() < DIGEST_LEN {
+            return Err(ErrorKinds::Invalid(format!("{s} is too short")));
+        }
+        let (size, n) = read_uvarint(&b[DIGEST_LEN..])?;
+        let id = &b[DIGEST_LEN + n..];
+        if id.len() != ID_LEN {
+            return Err(ErrorKinds::Invalid(format!(
+                "{s} contains an id of {} bytes",
+                id.len()
+            )));
+        }
+        Ok(ObjectHash {
+            digest: b[..DIGEST_LEN].to_vec(),
+            size,
+            qid: QId::new(&format!("iq__{}", encode_base58(id)))?,
+        })
+    }
+}
+
+/// PartHash is the decoded form of a part hash `hqp_...` or `hqpe...`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartHash {
+    pub encrypted: bool,
+    pub digest: Vec<u8>,
+    pub size: u64,
+}
+
+impl PartHash {
+    /// digest_hex returns the hex encoded sha256 digest
+    pub fn digest_hex(&self) -> String {
+        to_hex(&self.digest)
+    }
+
+    /// to_qphash encodes the digest and size as a part hash
+    pub fn to_qphash(&self) -> Result<QPartHash, ErrorKinds> {
+        QPartHash::new(&self.to_string())
+    }
+}
+
+impl FromStr for PartHash {
+    type Err = ErrorKinds;
+    fn from_str(s: &str) -> Result<PartHash, ErrorKinds> {
+        let (prefix, payload) = split_prefix(s, &["hqp_", "hqpe"])?;
+        let b = decode_base58(payload)?;
+        if b.len() < DIGEST_LEN {
+            return Err(ErrorKinds::Invalid(format!("{s} is too short")));
+        }
+        let (size, n) = read_uvarint(&b[DIGEST_LEN..])?;
+        if DIGEST_LEN + n != b.len() {
+            return Err(ErrorKinds::Invalid(format!("{s} has trailing bytes")));
+        }
+        Ok(PartHash {
+            encrypted: prefix == "hqpe",
+            digest: b[..DIGEST_LEN].to_vec(),
+            size,
+        })
+    }
+}
+
+impl fmt::Display for PartHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut b = self.digest.clone();
+        write_uvarint(&mut b, self.size);
+        let prefix = if self.encrypted { "hqpe" } else { "hqp_" };
+        write!(f, "{prefix}{}", encode_base58(&b))
+    }
+}
+
+/// WriteToken is the decoded form of a write token `tqw__...`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteToken {
+    pub qid: QId,
+    /// the id (`inod...`) of the node holding the draft
+    pub nid: String,
+    pub random: Vec<u8>,
+}
+
+/// typed_id_bytes returns the binary form of an id as found in tokens, its type code and address
+fn typed_id_bytes(id: &str, prefix: &str, code: u8) -> Result<Vec<u8>, ErrorKinds> {
+    let mut b = vec![code];
+    b.extend(id_bytes(id, prefix)?);
+    Ok(b)
+}
+
+/// typed_id_address checks the type code of the binary form of an id returning its address
+fn typed_id_address<'b>(b: &'b [u8], code: u8, token: &str) -> Result<&'b [u8], ErrorKinds> {
+    match b.split_first() {
+        Some((c, address)) if *c == code && address.len() == ID_LEN => Ok(address),
+        _ => Err(ErrorKinds::Invalid(format!(
+            "{token} contains malformed ids"
+        ))),
+    }
+}
+
+impl WriteToken {
+    /// to_qwtoken encodes the object id, node id and random bytes as a write token
+    pub fn to_qwtoken(&self) -> Result<QWriteToken, ErrorKinds> {
+        let mut b = Vec::new();
+        for field in [
+            typed_id_bytes(&self.qid, "iq__", CODE_QID)?,
+            typed_id_bytes(&self.nid, "inod", CODE_NID)?,
+            self.random.clone(),
+        ] {
+            write_uvarint(&mut b, field.len() as u64);
+            b.extend(field);
+        }
+        QWriteToken::new(&format!("tqw__{}", encode_base58(&b)))
+    }
+}
+
+fn read_field<'b>(b: &'b [u8], pos: &mut usize, token: &str) -> Result<&'b [u8], ErrorKinds> {
+    let (len, n) = read_uvarint(&b[*pos..])?;
+    let start = *pos + n;
+    let end = match start.checked_add(len as usize) {
+        Some(end) if end <= b.len() => end,
+        _ => return Err(ErrorKinds::Invalid(format!("{token} is truncated"))),
+    };
+    *pos = end;
+    Ok(&b[start..end])
+}
+
+impl FromStr for WriteToken {
+    type Err = ErrorKinds;
+    fn from_str(s: &str) -> Result<WriteToken, ErrorKinds> {
+        let (_, payload) = split_prefix(s, &["tqw__"])?;
+        let b = decode_base58(payload)?;
+        let mut pos = 0;
+        let qid = typed_id_address(read_field(&b, &mut pos, s)?, CODE_QID, s)?;
+        let nid = typed_id_address(read_field(&b, &mut pos, s)?, CODE_NID, s)?;
+        let random = read_field(&b, &mut pos, s)?;
+        if pos != b.len() {
+            return Err(ErrorKinds::Invalid(format!("{s} has trailing bytes")));
+        }
+        Ok(WriteToken {
+            qid: QId::new(&format!("iq__{}", encode_base58(qid)))?,
+            nid: format!("inod{}", encode_base58(nid)),
+            random: random.to_vec(),
+        })
+    }
+}
+
+impl QHash {
+    /// decode returns the digest, size and object id contained in the hash
+    pub fn decode(&self) -> Result<ObjectHash, ErrorKinds> {
+        self.parse()
+    }
+
+    /// qid returns the id of the content the hash is a version of
+    pub fn qid(&self) -> Result<QId, ErrorKinds> {
+        Ok(self.decode()?.qid)
+    }
+}
+
+impl QPartHash {
+    /// decode returns the digest and size contained in the part hash
+    pub fn decode(&self) -> Result<PartHash, ErrorKinds> {
+        self.parse()
+    }
+}
+
+impl QWriteToken {
+    /// decode returns the object id, node id and random bytes contained in the token
+    pub fn decode(&self) -> Result<WriteToken, ErrorKinds> {
+        self.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QHASH: &str =
+        "hq__9G79fi7NKap3TD29EZSFGJbZTpaK8cYXPdUk5TkMkxZGg8E8B5A3QfdmzRcjyZHLBYdDH4RMWn";
+    const QPHASH: &str = "hqpeojPFNiByiRr1BAQdfpxGxqwkdpncy7dDD1TVBuGaMrviJdRae";
+    // a token of the object of QHASH with node inod2XAaEKoLaTCy9JAuZF5K1ZW2yBY3 and 16 random bytes
+    const QWT: &str =
+        "tqw__HSZanpatHeCTnKisyqwKUS8BEN4hErCxtxJe9VyqAy8q75bHcDBUSoMBbmRPvyr4yDJp4PCUMvFw1BBoLV2";
+
+    #[test]
+    fn test_base58_round_trip() {
+        for data in [&b""[..], &[0, 0, 1, 2, 255], b"fabric"] {
+            assert_eq!(decode_base58(&encode_base58(data)).unwrap(), data);
+        }
+        assert_eq!(encode_base58(&[0, 0, 0x28, 0x7f, 0xb4, 0xcd]), "11233QC4");
+        assert!(decode_base58("0OIl").is_err());
+    }
+
+    #[test]
+    fn test_object_hash() {
+        let h: ObjectHash = QHASH.parse().unwrap();
+        assert_eq!(
+            h.digest_hex(),
+            "66743ab608333221b8ea32552986b44b1f2a7857d8891cff6150c37842c3cfef"
+        );
+        assert_eq!(h.size, 139);
+        assert_eq!(h.qid, "iq__4MyXwriZzDG25deHUFUNCDkmi6Az");
+        assert_eq!(h.to_qhash().unwrap(), QHASH);
+        let qhash: QHash = QHASH.parse().unwrap();
+        assert_eq!(qhash.qid().unwrap(), h.qid);
+    }
+
+    #[test]
+    fn test_part_hash() {
+        let p: PartHash = QPHASH.parse().unwrap();
+        assert!(p.encrypted);
+        assert_eq!(
+            p.digest_hex(),
+            "6a1b9e89cb3a452f7fecdb880ae13e8bfcc5d2bd3bff142f0fdd5226f1e8f1a3"
+        );
+        assert_eq!(p.size, 32471520);
+        assert_eq!(p.to_string(), QPHASH);
+        let clear = PartHash {
+            encrypted: false,
+            ..p
+        };
+        assert!(clear.to_string().starts_with("hqp_"));
+        assert_eq!(clear.to_string().parse::<PartHash>().unwrap(), clear);
+    }
+
+    #[test]
+    fn test_write_token_and_address() {
+        let qid: QId = "iq__4MyXwriZzDG25deHUFUNCDkmi6Az".parse().unwrap();
+        assert_eq!(
+            qid.address().unwrap(),
+            "0xf14b4d45208566b3b089440f769d9c6e4622967f"
+        );
+        assert_eq!(
+            QId::from_address("0xf14b4d45208566b3b089440f769d9c6e4622967f").unwrap(),
+            qid
+        );
+        let lib: QLibId = "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ".parse().unwrap();
+        assert_eq!(QLibId::from_address(&lib.address().unwrap()).unwrap(), lib);
+
+        let t = WriteToken {
+            qid: qid.clone(),
+            nid: format!("inod{}", encode_base58(&[7; ID_LEN])),
+            random: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        };
+        let qwt = t.to_qwtoken().unwrap();
+        assert_eq!(qwt.decode().unwrap(), t);
+        assert!("tqw__2a".parse::<WriteToken>().is_err());
+    }
+
+    #[test]
+    fn test_write_token_vector() {
+        let t: WriteToken = QWT.parse().unwrap();
+        assert_eq!(t.qid, "iq__4MyXwriZzDG25deHUFUNCDkmi6Az");
+        assert_eq!(t.nid, "inod2XAaEKoLaTCy9JAuZF5K1ZW2yBY3");
+        assert_eq!(
+            t.random,
+            [
+                0x3a, 0x9e, 0x1f, 0x04, 0xc2, 0x7b, 0x58, 0xd6, 0xe0, 0xa4, 0x91, 0x7f, 0x2c, 0x6b,
+                0x8d, 0x15
+            ]
+        );
+        assert_eq!(t.to_qwtoken().unwrap(), QWT);
+        // uvarint length, type code and address of the object id lead the token
+        let b = decode_base58(QWT.strip_prefix("tqw__").unwrap()).unwrap();
+        assert_eq!(b[..2], [21, CODE_QID]);
+        assert_eq!(b[2..22], address_bytes(&t.qid.address().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_address_not_hex() {
+        // 40 bytes long but the multibyte chars would split a slice of two bytes
+        let address = format!("0x{}", "é".repeat(20));
+        assert_eq!(address.len(), 42);
+        assert!(matches!(
+            QId::from_address(&address),
+            Err(ErrorKinds::Invalid(_))
+        ));
+        let address = format!("0x{}", "g".repeat(40));
+        assert!(matches!(
+            QLibId::from_address(&address),
+            Err(ErrorKinds::Invalid(_))
+        ));
+    }
+}
@@ -52,7 +52,13 @@
     *cargo build --target wasm32-unknown-unknown* <br>
   <br>
   test <br>
-    *target/debug/mock ./samples/target/wasm32-unknown-unknown/debug/deps/rproxy.wasm ./samples/fabric.json*
+    *target/debug/mock run ./samples/target/wasm32-unknown-unknown/debug/deps/rproxy.wasm ./samples/fabric.json* <br>
+  or call a handler with a crafted request, saving the output stream <br>
+    *target/debug/mock -o out.json call ./samples/target/wasm32-unknown-unknown/debug/deps/rproxy.wasm --fabric ./samples/fabric.json --method proxy --query QUERY=fabric*
+  or serve the handlers over http at 127.0.0.1:8080 <br>
+    *target/debug/mock serve ./samples/target/wasm32-unknown-unknown/debug/deps/rproxy.wasm --fabric ./samples/fabric.json*
+  or count the host calls of the objtar workload for each way of writing the output stream <br>
+    *target/debug/mock bench --fabric ./samples/fixture --parts 16 --batch 1048576*
 */
 
 extern crate serde;
@@ -67,11 +73,24 @@ pub mod bccontext_core;
 pub mod bccontext_error;
 pub mod bccontext_ext;
 pub mod bccontext_fabric_io;
+pub mod bccontext_ids;
+pub mod bccontext_middleware;
+pub mod bccontext_router;
 pub mod bccontext_search;
 pub mod bccontext_struct;
+#[cfg(any(test, feature = "testing"))]
+pub mod bccontext_testing;
+pub mod bccontext_typed;
+pub mod bccontext_typed_api;
+pub mod fabric_id;
 
 pub use self::bccontext::*;
 pub use self::bccontext_error::*;
+pub use self::bccontext_ids::{
+    HashOrToken, IdHashOrToken, QHash, QId, QLibId, QPartHash, QStateStoreId, QWriteToken,
+};
+pub use self::bccontext_middleware::{Middleware, MiddlewareChain};
+pub use self::bccontext_router::Router;
 pub use self::bccontext_struct::*;
 
 use std::str;
@@ -82,14 +101,10 @@ use std::collections::HashMap;
 use lazy_static::lazy_static;
 use std::sync::Mutex;
 
-#[derive(Clone)]
-struct HandlerData<'a> {
-    pub hf: HandlerFunction<'a>,
-    pub req: Option<BitcodeContext>,
-}
-
 lazy_static! {
-    static ref CALLMAP: Mutex<HashMap<String, HandlerData<'static>>> = Mutex::new(HashMap::new());
+    static ref CALLMAP: Mutex<HashMap<String, HandlerFunction>> = Mutex::new(HashMap::new());
+    static ref ROUTER: Mutex<Router> = Mutex::new(Router::new());
+    static ref MIDDLEWARE: Mutex<MiddlewareChain> = Mutex::new(MiddlewareChain::new());
 }
 
 mod version {
@@ -107,6 +122,12 @@ pub fn get_library_version() -> String {
     format!("cargo:{}--git:{}", get_cargo_version(), get_git_version())
 }
 
+/// library_version is registered by [implement_bitcode_module] as the waPC function `_version` so that a
+/// host can report the library a loaded module was built with
+pub fn library_version(_msg: &[u8]) -> CallResult {
+    Ok(get_library_version().into_bytes())
+}
+
 #[macro_export]
 macro_rules! register_handlers {
   () => {};
@@ -117,6 +138,15 @@ macro_rules! register_handlers {
   }
 }
 
+#[macro_export]
+macro_rules! register_routes {
+  () => {};
+  ($verb:literal $pattern:literal => $route_func:ident $(, $more_verb:literal $more_pattern:literal => $more_func:ident )* $(,)?) => {
+    $crate::register_route($verb, $pattern, $route_func);
+    $crate::register_routes!($( $more_verb $more_pattern => $more_func ),* );
+  }
+}
+
 /// This macro delivers the required initializtion of the eluvio wasm module
 /// In addition the macro also registers a handler of the form
 /// ```ignore
@@ -130,9 +160,37 @@ macro_rules! register_handlers {
 ///   return bcc.make_success("SUCCESS");
 /// }
 /// ```
+/// Handlers may additionally be routed by verb and path pattern (see [bccontext_router]) with an
+/// optional default handler for requests matching neither a route nor a method
+/// ```ignore
+/// implement_bitcode_module!("image", do_image;
+///   routes {
+///     "GET" "/image/{offering}/assets/*path" => do_asset,
+///     "*" "/image/{offering}" => do_image,
+///   },
+///   default => do_not_found
+/// );
+/// ```
+/// Typed handlers taking deserialized arguments and returning a serializable result (see [bccontext_typed])
+/// are registered by method name in a `typed` section
+/// ```ignore
+/// implement_bitcode_module!("image", do_image;
+///   typed { "info" => do_info }
+/// );
+/// fn do_info(bcc: &mut elvwasm::BitcodeContext, args: InfoArgs) -> Result<Info, ErrorKinds> {
+///   Ok(Info { height: args.height })
+/// }
+/// ```
+/// and wrapped by [Middleware] run around every handler (see [bccontext_middleware])
+/// ```ignore
+/// use elvwasm::bccontext_middleware::RequestLogger;
+/// implement_bitcode_module!("image", do_image;
+///   middleware [RequestLogger]
+/// );
+/// ```
 #[macro_export]
 macro_rules! implement_bitcode_module {
-  ($handler_name:literal, $handler_func:ident $(, $more_lit:literal, $more:ident)*) => {
+  (@init { $($register:tt)* }) => {
     extern crate wapc_guest as guest;
 
     use guest::{register_function, CallResult, console_log};
@@ -142,8 +200,9 @@ macro_rules! implement_bitcode_module {
 
     #[no_mangle]
     pub extern "C" fn wapc_init() {
-      register_handlers!($handler_name, $handler_func $(, $more_lit, $more)*);
+      $($register)*
       register_function("_JPC", jpc);
+      register_function("_version", $crate::library_version);
       panic::set_hook(Box::new(|panic_info| {
             if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
                 let _ = console_log(&format!("Panic in WASM!! {0}", s));
@@ -154,7 +213,25 @@ macro_rules! implement_bitcode_module {
             }
         }));
     }
-  }
+  };
+  ($handler_name:literal, $handler_func:ident $(, $more_lit:literal, $more:ident)*) => {
+    $crate::implement_bitcode_module!(@init {
+      register_handlers!($handler_name, $handler_func $(, $more_lit, $more)*);
+    });
+  };
+  ($handler_name:literal, $handler_func:ident $(, $more_lit:literal, $more:ident)*;
+    $(typed { $($typed_lit:literal => $typed_func:ident),* $(,)? })? $(,)?
+    $(routes { $($verb:literal $pattern:literal => $route_func:ident),* $(,)? })? $(,)?
+    $(middleware [ $($mw:expr),* $(,)? ])? $(,)?
+    $(default => $default_func:ident)? $(,)?) => {
+    $crate::implement_bitcode_module!(@init {
+      register_handlers!($handler_name, $handler_func $(, $more_lit, $more)*);
+      $($($crate::register_handler($typed_lit, $crate::typed_handler!($typed_func));)*)?
+      $($crate::register_routes!($($verb $pattern => $route_func),*);)?
+      $($($crate::register_middleware($mw);)*)?
+      $($crate::register_default_handler($default_func);)?
+    });
+  };
 }
 #[cfg(target_os = "linux")]
 mod c_exports {
@@ -251,6 +328,144 @@ mod c_exports {
     }
 }
 
+/// Bitcode handler signature used by the method map, the router and the middleware chain
+pub type HandlerFunction = fn(bcc: &mut BitcodeContext) -> CallResult;
+
+/// register_handler adjusts the global static call map to associate a bitcode module with a path
+/// this map is used by jpc to implement bitcode calls
+#[no_mangle]
+pub fn register_handler(name: &str, h: HandlerFunction) {
+    match CALLMAP.lock().as_mut() {
+        Ok(x) => {
+            x.insert(name.to_string(), h);
+        }
+        Err(e) => console_log(&format!("MutexGuard unable to aquire lock, error = {e}")),
+    };
+}
+
+/// register_route adjusts the global static router to associate a handler with a verb and path pattern
+/// (see [bccontext_router]).  Routes are consulted by jpc before the method names from [register_handler]
+pub fn register_route(verb: &str, pattern: &str, h: HandlerFunction) {
+    match ROUTER.lock().as_mut() {
+        Ok(r) => {
+            if let Err(e) = r.add_route(verb, pattern, h) {
+                console_log(&format!("unable to register route {pattern}, error = {e}"));
+            }
+        }
+        Err(e) => console_log(&format!("MutexGuard unable to aquire lock, error = {e}")),
+    };
+}
+
+/// register_default_handler sets the handler jpc calls when neither a route nor a method matches the request
+pub fn register_default_handler(h: HandlerFunction) {
+    match ROUTER.lock().as_mut() {
+        Ok(r) => r.set_default(h),
+        Err(e) => console_log(&format!("MutexGuard unable to aquire lock, error = {e}")),
+    };
+}
+
+/// register_middleware appends a [Middleware] to the global chain run by jpc around every handler.
+/// Middleware `before` hooks run in registration order and `after` hooks in reverse order
+pub fn register_middleware<M: Middleware + 'static>(m: M) {
+    match MIDDLEWARE.lock().as_mut() {
+        Ok(c) => c.push(m),
+        Err(e) => console_log(&format!("MutexGuard unable to aquire lock, error = {e}")),
+    };
+}
+
+const ID_NOT_CALCULATED_YET: &str = "id not yet calculated";
+
+/// resolve_handler selects the handler for a request; routes take precedence over method names which in
+/// turn take precedence over the default handler
+fn resolve_handler(
+    json_params: &Request,
+) -> Result<(HandlerFunction, HashMap<String, String>), ErrorKinds> {
+    let http = &json_params.params.http;
+    let (routed, default_handler) = match ROUTER.lock() {
+        Ok(r) => (r.route(&http.verb, &http.path), r.default_handler()),
+        Err(e) => {
+            return Err(ErrorKinds::BadHttpParams(format!(
+                "unable to gain access to router: error = {e}"
+            )))
+        }
+    };
+    if let Some(m) = routed {
+        return Ok((m.handler, m.params));
+    }
+    let method_handler = match CALLMAP.lock() {
+        Ok(cm) => cm.get(json_params.method.as_str()).copied(),
+        Err(e) => {
+            return Err(ErrorKinds::BadHttpParams(format!(
+                "unable to gain access to callmap: error = {e}"
+            )))
+        }
+    };
+    match method_handler.or(default_handler) {
+        Some(h) => Ok((h, HashMap::new())),
+        None => Err(ErrorKinds::Invalid(format!(
+            "handler not found {}",
+            json_params.method
+        ))),
+    }
+}
+
+/// do_bitcode dispatches a parsed request.  The [BitcodeContext] is owned by this call frame and lent to
+/// the middleware chain and handler for the duration of the call, it is dropped exactly once on return.
+fn do_bitcode(json_params: Request) -> CallResult {
+    console_log("Parameters parsed");
+    let chain = match MIDDLEWARE.lock() {
+        Ok(c) => c.clone(),
+        Err(e) => {
+            return make_json_error(
+                ErrorKinds::BadHttpParams(format!(
+                    "unable to gain access to middleware: error = {e}"
+                )),
+                ID_NOT_CALCULATED_YET,
+            )
+        }
+    };
+    let (handler, path_params) = match resolve_handler(&json_params) {
+        Ok(h) => h,
+        Err(e @ ErrorKinds::Invalid(_)) => return Err(Box::new(e)),
+        Err(e) => return make_json_error(e, ID_NOT_CALCULATED_YET),
+    };
+    let id = json_params.id.clone();
+    let mut bcc = BitcodeContext {
+        request: json_params,
+        path_params,
+    };
+    match chain.run(&mut bcc, handler) {
+        Ok(o) => Ok(o),
+        Err(e) => make_json_error(FabricError::from(e), &id),
+    }
+}
+
+/// jpc is the main entry point into a wasm bitcode for the web assembly procedure calls
+/// this function will
+/// # Steps
+///   * parse the input for the appropriately formatted json
+///   * construct a BitcodeContext from the json
+///   * attempt to call the method using the incomming path
+///   * return results to the caller
+#[no_mangle]
+pub fn jpc(_msg: &[u8]) -> CallResult {
+    console_log("In jpc");
+    let input_string = str::from_utf8(_msg)?;
+    console_log(&format!("parameters = {input_string}"));
+    let json_params: Request = match serde_json::from_str(input_string) {
+        Ok(m) => m,
+        Err(err) => {
+            return make_json_error(
+                ErrorKinds::Invalid(format!("parse failed for http error = {err}")),
+                ID_NOT_CALCULATED_YET,
+            );
+        }
+    };
+
+    console_log("Request parsed");
+    do_bitcode(json_params)
+}
+
 // The following are mearly intended to verify internal consistency.  There are no actual calls made
 // but the tests verify that the json parsing of the http message is correct
 #[cfg(test)]
@@ -259,6 +474,7 @@ mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     pub use self::bccontext::*;
     use super::*;
+    use crate::bccontext_testing::ScriptedHost;
     use serde_json::*;
 
     fn handler_for_test(bcc: &mut BitcodeContext) -> CallResult {
@@ -299,6 +515,107 @@ mod tests {
         };
     }
 
+    fn route_handler_for_test(bcc: &mut BitcodeContext) -> CallResult {
+        let name = bcc.path_param("name").unwrap_or_default().to_string();
+        bcc.make_success(&name)
+    }
+
+    #[test]
+    fn test_routed_http() {
+        register_route("GET", "/routed/{name}/*rest", route_handler_for_test);
+        let test_json = json!({
+          "id" : "dummydummy",
+          "jpc" : "1.0",
+          "method" : "not_registered",
+          "params" : {
+            "http" : {
+              "path" : "/routed/birds/robin.jpg",
+              "verb" : "GET",
+            },
+          },
+          "qinfo" : {
+            "qlib_id" : "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ",
+            "type" : "some_type",
+          },
+        });
+        let res = jpc(&serde_json::to_vec(&test_json).unwrap()).unwrap();
+        let res_json: serde_json::Value = serde_json::from_slice(&res).unwrap();
+        assert_eq!(res_json["result"], "birds");
+    }
+
+    fn handler_mutating_context(bcc: &mut BitcodeContext) -> CallResult {
+        bcc.log_debug("owned context")?;
+        let key = format!("call{}", bcc.path_params.len());
+        bcc.path_params.insert(key, bcc.request.id.clone());
+        bcc.request.id.push_str("-mutated");
+        let count = bcc.path_params.len().to_string();
+        bcc.make_success(&count)
+    }
+
+    // Each call owns a fresh context that is dropped on return.  The host calls go to an in-memory host so
+    // the test runs under `cargo miri test`, whose leak check fails the run if a context is leaked and
+    // which reports a context freed twice as undefined behavior.
+    #[test]
+    fn test_context_owned_per_call() {
+        register_handler("owned_context", handler_mutating_context);
+        let host = ScriptedHost::new().respond("ctx", "Log", json!({}));
+        let _guard = host.install();
+        for i in 0..4 {
+            let test_json = json!({
+              "id" : format!("id{i}"),
+              "jpc" : "1.0",
+              "method" : "owned_context",
+              "params" : {
+                "http" : {
+                  "path" : "/owned_context",
+                },
+              },
+              "qinfo" : {
+                "qlib_id" : "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ",
+                "type" : "some_type",
+              },
+            });
+            let res = jpc(&serde_json::to_vec(&test_json).unwrap()).unwrap();
+            let res_json: serde_json::Value = serde_json::from_slice(&res).unwrap();
+            assert_eq!(res_json["result"], "1");
+            assert_eq!(res_json["id"], format!("id{i}-mutated"));
+        }
+        // each call logged with the id of its own request rather than a context left by the previous call
+        let bindings: Vec<String> = host
+            .calls_to("ctx", "Log")
+            .into_iter()
+            .map(|c| c.binding)
+            .collect();
+        assert_eq!(bindings, vec!["id0", "id1", "id2", "id3"]);
+    }
+
+    fn handler_not_exist(_bcc: &mut BitcodeContext) -> CallResult {
+        Err(Box::new(ErrorKinds::NotExist("nothing here".to_string())))
+    }
+
+    #[test]
+    fn test_handler_error_kind() {
+        register_handler("not_exist", handler_not_exist);
+        let test_json = json!({
+          "id" : "dummydummy",
+          "jpc" : "1.0",
+          "method" : "not_exist",
+          "params" : {
+            "http" : {
+              "path" : "/not_exist",
+            },
+          },
+          "qinfo" : {
+            "qlib_id" : "ilib4M649Yi6tCTWpXgxch4i9RJvv4BQ",
+            "type" : "some_type",
+          },
+        });
+        let res = jpc(&serde_json::to_vec(&test_json).unwrap()).unwrap();
+        let res_json: serde_json::Value = serde_json::from_slice(&res).unwrap();
+        assert_eq!(res_json["error"]["op"], 6);
+        assert_eq!(res_json["error"]["status"], 404);
+    }
+
     #[test]
     fn test_basic_http_failure() {
         register_handler("test_handler", handler_for_test);
@@ -338,105 +655,3 @@ mod tests {
         };
     }
 }
-
-type HandlerFunction<'a> = fn(bcc: &'a mut BitcodeContext) -> CallResult;
-
-/// register_handler adjusts the global static call map to associate a bitcode module with a path
-/// this map is used by jpc to implement bitcode calls
-#[no_mangle]
-pub fn register_handler(name: &str, h: HandlerFunction<'static>) {
-    let hd = HandlerData { hf: h, req: None };
-    match CALLMAP.lock().as_mut() {
-        Ok(x) => {
-            x.insert(name.to_string(), hd);
-        }
-        Err(e) => console_log(&format!("MutexGuard unable to aquire lock, error = {e}")),
-    };
-}
-
-const ID_NOT_CALCULATED_YET: &str = "id not yet calculated";
-
-fn do_bitcode(json_params: Request) -> CallResult {
-    console_log("Parameters parsed");
-    let method: &str = json_params.method.as_str();
-
-    let mut v_leaks: Vec<Box<BitcodeContext>> = Vec::<Box<BitcodeContext>>::new();
-
-    let cm = match CALLMAP.lock() {
-        Ok(c) => c,
-        Err(e) => {
-            return make_json_error(
-                ErrorKinds::BadHttpParams(format!("unable to gain access to callmap: error = {e}")),
-                ID_NOT_CALCULATED_YET,
-            )
-        }
-    };
-    // let mut element = 1;
-    // if json_params.method == "content"{
-    //   element = 0;
-    // }
-    let mut bind = cm.get(method).into_iter();
-    let cm_handler = match bind.find(|mut _x| true).as_mut() {
-        Some(b) => b.to_owned(),
-        None => {
-            return Err(Box::new(ErrorKinds::Invalid(format!(
-                "handler not found {}",
-                method
-            ))))
-        }
-    };
-    match cm_handler.req {
-        Some(f) => {
-            let id = f.request.id.to_string();
-            let bcc = Box::new(f);
-            let l = Box::leak(bcc);
-            unsafe {
-                v_leaks.push(Box::from_raw(l));
-            }
-            match (cm_handler.hf)(l) {
-                Ok(o) => Ok(o),
-                Err(e) => make_json_error(ErrorKinds::Other(e.to_string()), &id),
-            }
-        }
-        None => {
-            let bcc = BitcodeContext {
-                request: json_params.clone(),
-            };
-            let id = bcc.request.id.clone();
-            let l = Box::leak(Box::new(bcc));
-            unsafe {
-                v_leaks.push(Box::from_raw(l));
-            }
-            match (cm_handler.hf)(l) {
-                Ok(o) => Ok(o),
-                Err(e) => make_json_error(ErrorKinds::Other(e.to_string()), &id),
-            }
-        }
-    }
-}
-
-/// jpc is the main entry point into a wasm bitcode for the web assembly procedure calls
-/// this function will
-/// # Steps
-///   * parse the input for the appropriately formatted json
-///   * construct a BitcodeContext from the json
-///   * attempt to call the method using the incomming path
-///   * return results to the caller
-#[no_mangle]
-pub fn jpc(_msg: &[u8]) -> CallResult {
-    console_log("In jpc");
-    let input_string = str::from_utf8(_msg)?;
-    console_log(&format!("parameters = {input_string}"));
-    let json_params: Request = match serde_json::from_str(input_string) {
-        Ok(m) => m,
-        Err(err) => {
-            return make_json_error(
-                ErrorKinds::Invalid(format!("parse failed for http error = {err}")),
-                ID_NOT_CALCULATED_YET,
-            );
-        }
-    };
-
-    console_log("Request parsed");
-    do_bitcode(json_params)
-}